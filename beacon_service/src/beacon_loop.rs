@@ -1,49 +1,207 @@
 use crate::discord;
+use crate::leader;
+use crate::notification_policy::{self, NotificationKind};
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, SystemTime};
+
+use db_service::riders::{Rider, RiderStatus};
 use strava_service::beacon::{BeaconData, Status};
+use tokio::sync::mpsc;
+
+// how long to wait before polling the beacon again
+const ACTIVE_POLL_INTERVAL: Duration = Duration::from_secs(15);
+const WAITING_POLL_INTERVAL: Duration = Duration::from_secs(2 * 60);
+const IDLE_POLL_INTERVAL: Duration = Duration::from_secs(5 * 60);
+const FOLLOWER_POLL_INTERVAL: Duration = Duration::from_secs(30);
+const ERROR_BACKOFF_BASE: Duration = Duration::from_secs(45);
+const ERROR_BACKOFF_MAX: Duration = Duration::from_secs(10 * 60);
+// a rider whose on trail status hasn't been refreshed by a poll in this long is taken off the trails
+const STALE_ON_TRAIL: Duration = Duration::from_secs(4 * 60 * 60);
+// how often to check the db for new riders and for beacon urls that came in through other instances
+const RIDER_CHECK_INTERVAL: Duration = Duration::from_secs(15);
+
+// wake up channels for each rider's beacon loop, keyed by rider id
+static WAKE_SENDERS: LazyLock<Mutex<HashMap<i64, mpsc::Sender<()>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+// result of a single beacon poll, decides how soon the next poll happens
+enum PollOutcome {
+    // ride in progress, poll often to keep up with it
+    Active,
+    // beacon exists but the ride hasn't started or its upload hasn't shown up yet
+    Waiting,
+    // no beacon to poll
+    Idle,
+    // strava (or something else) failed, back off
+    Error,
+}
+
+impl From<&Status> for PollOutcome {
+    fn from(status: &Status) -> Self {
+        match status {
+            Status::Active | Status::AutoPaused | Status::ManualPaused => PollOutcome::Active,
+            Status::Uploaded | Status::Discarded => PollOutcome::Idle,
+            _ => PollOutcome::Waiting,
+        }
+    }
+}
 
-// loop that continuously checks the db for a beacon url and processes the data if found
+// wakes a rider's beacon loop up immediately, e.g. when a new beacon url comes in
+pub fn wake(rider_id: i64) {
+    let senders = WAKE_SENDERS.lock().unwrap();
+    match senders.get(&rider_id) {
+        // a full channel means a wake up is already pending
+        Some(sender) => {
+            let _ = sender.try_send(());
+        }
+        None => tracing::debug!(
+            "No beacon loop running for rider {}, nothing to wake",
+            rider_id
+        ),
+    }
+}
+
+// starts a beacon loop for every registered rider
+// only the instance holding the beacon lease does any processing
 pub fn start() {
-    match (std::env::var("FLY_REGION"), std::env::var("PRIMARY_REGION")) {
-        (Ok(fly_region), Ok(primary_region)) => {
-            if fly_region == primary_region {
-                tracing::info!("Beacon loop running in region: {}", fly_region);
+    leader::start();
+    start_rider_watcher();
+}
+
+// keeps a beacon loop running for every registered rider, including riders registered after startup
+// a beacon url can land on any instance but wake() only reaches loops in this process,
+// so the leader also watches the db for beacon url changes and wakes its own loops for them
+fn start_rider_watcher() {
+    tokio::spawn(async move {
+        let mut is_first_check = true;
+        let mut last_seen: Option<HashMap<i64, i64>> = None;
+        loop {
+            // syncs with the remote primary, so riders and beacon urls from other instances show up
+            let updates = db_service::riders::get_beacon_url_updates().await;
+
+            let riders = db_service::riders::get_riders().await;
+            if is_first_check && riders.is_empty() {
+                tracing::warn!("No riders registered, beacon loop has nothing to do");
+            }
+            is_first_check = false;
+            for rider in riders {
+                start_rider_loop(rider);
+            }
+
+            if leader::is_leader() {
+                if let Some(last_seen) = &last_seen {
+                    for (rider_id, updated_at) in &updates {
+                        if last_seen.get(rider_id) != Some(updated_at) {
+                            tracing::debug!("Beacon url changed for rider {}, waking", rider_id);
+                            wake(*rider_id);
+                        }
+                    }
+                }
+                last_seen = Some(updates);
             } else {
-                tracing::trace!(
-                    "Fly region ({}) and primary region ({}) do not match, skipping beacon loop",
-                    fly_region,
-                    primary_region
-                );
-                return;
+                // start from a fresh snapshot if this instance becomes the leader later
+                last_seen = None;
             }
+
+            tokio::time::sleep(RIDER_CHECK_INTERVAL).await;
         }
-        _ => {
-            tracing::warn!("FLY_REGION and PRIMARY_REGION are not both set, running beacon loop");
+    });
+}
+
+// loop that continuously checks the db for the rider's beacon url and processes the data if found
+fn start_rider_loop(rider: Rider) {
+    let (sender, mut receiver) = mpsc::channel::<()>(1);
+    {
+        let mut senders = WAKE_SENDERS.lock().unwrap();
+        if senders.contains_key(&rider.id) {
+            tracing::trace!("Beacon loop already started for {}", rider.name);
+            return;
         }
+        senders.insert(rider.id, sender);
     }
 
+    tracing::info!("Starting beacon loop for {}", rider.name);
+
     tokio::spawn(async move {
+        let mut consecutive_errors: u32 = 0;
         loop {
-            process_beacon().await;
-            tokio::time::sleep(tokio::time::Duration::from_secs(45)).await;
+            let interval = if leader::is_leader() {
+                match process_beacon(&rider).await {
+                    PollOutcome::Error => {
+                        consecutive_errors += 1;
+                        error_backoff(consecutive_errors)
+                    }
+                    outcome => {
+                        consecutive_errors = 0;
+                        match outcome {
+                            PollOutcome::Active => ACTIVE_POLL_INTERVAL,
+                            PollOutcome::Waiting => WAITING_POLL_INTERVAL,
+                            _ => IDLE_POLL_INTERVAL,
+                        }
+                    }
+                }
+            } else {
+                tracing::trace!("Not the beacon leader, skipping beacon processing");
+                FOLLOWER_POLL_INTERVAL
+            };
+
+            tracing::trace!("Next beacon poll for {} in {:?}", rider.name, interval);
+            tokio::select! {
+                _ = tokio::time::sleep(interval) => {}
+                _ = receiver.recv() => {
+                    tracing::debug!("Beacon loop for {} woken up early", rider.name);
+                }
+            }
         }
     });
 }
 
-async fn process_beacon() {
-    let troy_status = db_service::get_troy_status().await;
+// exponential backoff with up to 25% jitter so retries don't line up with strava's rate limit windows
+fn error_backoff(consecutive_errors: u32) -> Duration {
+    let backoff = ERROR_BACKOFF_BASE
+        .saturating_mul(2u32.saturating_pow(consecutive_errors.saturating_sub(1)))
+        .min(ERROR_BACKOFF_MAX);
+
+    let jitter_fraction = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.subsec_nanos() % 1000)
+        .unwrap_or_default() as f64
+        / 4000.0;
+
+    backoff + backoff.mul_f64(jitter_fraction)
+}
+
+async fn process_beacon(rider: &Rider) -> PollOutcome {
+    let rider_status = db_service::riders::get_rider_status(rider.id).await;
+
+    let is_stale = rider_status
+        .trail_status_updated
+        .and_then(|updated| updated.elapsed().ok())
+        .is_some_and(|elapsed| elapsed > STALE_ON_TRAIL);
+    if rider_status.is_on_trail && is_stale {
+        tracing::warn!(
+            "{}'s on trail status hasn't been updated in {:?}, taking them off the trails",
+            rider.name,
+            STALE_ON_TRAIL
+        );
+        end_session(rider, &rider_status).await;
+        return PollOutcome::Idle;
+    }
 
-    let beacon_url = match troy_status.beacon_url {
-        Some(url) => url,
+    let beacon_url = match &rider_status.beacon_url {
+        Some(url) => url.clone(),
         None => {
-            if troy_status.is_on_trail {
+            if rider_status.is_on_trail {
                 tracing::warn!(
-                "Troy status indicates on the trails but no beacon url found, clearing troy status"
-            );
-                db_service::set_troy_status(false).await;
+                    "{} is on the trails but no beacon url found, clearing rider status",
+                    rider.name
+                );
+                end_session(rider, &rider_status).await;
             } else {
-                tracing::debug!("No beacon url found, troy is not on the trails");
+                tracing::debug!("No beacon url found, {} is not on the trails", rider.name);
             }
-            return;
+            return PollOutcome::Idle;
         }
     };
 
@@ -51,19 +209,28 @@ async fn process_beacon() {
         Ok(data) => data,
         Err(e) if e.to_string().contains("404 Not Found") => {
             tracing::warn!("Beacon data not found (404 Not Found), clearing beacon url");
-            db_service::set_beacon_url(None).await;
-            return;
+            db_service::riders::set_beacon_url(rider.id, None).await;
+            return PollOutcome::Idle;
         }
         Err(e) => {
             tracing::error!("Failed to get beacon data: {}", e);
-            return;
+            return PollOutcome::Error;
         }
     };
 
+    let current_location = beacon_data.last_latlng();
+    db_service::beacon_tracks::add_points(
+        rider.id,
+        beacon_data.live_activity_id,
+        &beacon_data.track_points(),
+    )
+    .await;
+
     let BeaconData {
         status,
         activity_id,
         update_time,
+        stats,
         ..
     } = match (beacon_data.activity_id, &beacon_data.status) {
         // has activity_id, status is already uploaded or discarded
@@ -90,31 +257,109 @@ async fn process_beacon() {
         (now - update_time).num_minutes()
     };
 
+    let outcome = PollOutcome::from(&status);
+
     match status {
         Status::Active | Status::AutoPaused | Status::ManualPaused => {
-            tracing::trace!("Beacon data indicates troy is active on the trails");
-            db_service::set_troy_status(true).await;
-            if !troy_status.is_on_trail {
-                tracing::info!("Troy status updated to on the trails");
-                discord::send_starting_webhook(beacon_url).await;
+            tracing::trace!(
+                "Beacon data indicates {} is active on the trails",
+                rider.name
+            );
+            db_service::riders::set_on_trail(rider.id, true).await;
+
+            let trails = trail_service::trail_data::get_data().await.trail_data;
+            let previous_trail = rider_status
+                .current_trail_id
+                .and_then(|id| trails.iter().find(|trail| trail.id == id));
+            let current_trail = current_location.and_then(|(lat, lng)| {
+                trail_service::trail_lookup::find_trail_at(&trails, lat, lng)
+            });
+
+            if !rider_status.session_confirmed {
+                // hold the start notification until the ride has gone on long enough to be real
+                let current_trail_id = current_trail.map(|trail| trail.id);
+                if rider_status.current_trail_id != current_trail_id {
+                    db_service::riders::set_current_trail(rider.id, current_trail_id).await;
+                }
+                let policy = notification_policy::get_policy();
+                if !policy.is_session_confirmed(stats.elapsed_time) {
+                    tracing::debug!("Ride session not confirmed yet, holding start notification");
+                    return PollOutcome::Active;
+                }
+                if policy.should_hold_start(rider) {
+                    tracing::debug!("Quiet hours, holding start notification until they end");
+                    return PollOutcome::Active;
+                }
+
+                tracing::info!("{} status updated to on the trails", rider.name);
+                db_service::riders::set_session_confirmed(rider.id, true).await;
+                if notification_policy::should_send(NotificationKind::Start, rider, &rider_status)
+                    .await
+                {
+                    discord::send_starting_webhook(
+                        rider,
+                        beacon_url,
+                        current_trail.map(|trail| trail.name.clone()),
+                    )
+                    .await;
+                }
+            } else if let Some(current_trail) = current_trail {
+                // only announce arriving at a trail, leaving a trail's radius keeps the last known trail
+                if rider_status.current_trail_id != Some(current_trail.id) {
+                    tracing::info!(
+                        "{} is now at trail system: {}",
+                        rider.name,
+                        current_trail.name
+                    );
+                    db_service::riders::set_current_trail(rider.id, Some(current_trail.id)).await;
+                    if notification_policy::should_send(
+                        NotificationKind::TrailChange(current_trail.id),
+                        rider,
+                        &rider_status,
+                    )
+                    .await
+                    {
+                        discord::send_trail_change_webhook(
+                            rider,
+                            beacon_url,
+                            current_trail.name.clone(),
+                            previous_trail.map(|trail| trail.name.clone()),
+                        )
+                        .await;
+                    }
+                }
             }
         }
         Status::Uploaded => {
             tracing::info!("Beacon data indicates activity uploaded, clearing beacon url");
-            db_service::set_beacon_url(None).await;
-            if troy_status.is_on_trail {
-                db_service::set_troy_status(false).await;
-                discord::send_end_webhook(activity_id).await;
+            db_service::riders::set_beacon_url(rider.id, None).await;
+            if rider_status.is_on_trail {
+                end_session(rider, &rider_status).await;
+                if rider_status.session_confirmed
+                    && notification_policy::should_send(NotificationKind::End, rider, &rider_status)
+                        .await
+                {
+                    discord::send_end_webhook(rider, activity_id).await;
+                }
             }
         }
         Status::Discarded => {
             tracing::info!(
-                "Beacon data indicates activity was discarded, clearing troy status and beacon url"
+                "Beacon data indicates activity was discarded, clearing rider status and beacon url"
             );
-            db_service::set_beacon_url(None).await;
-            if troy_status.is_on_trail {
-                db_service::set_troy_status(false).await;
-                discord::send_discard_webhook().await;
+            db_service::riders::set_beacon_url(rider.id, None).await;
+            if rider_status.is_on_trail {
+                end_session(rider, &rider_status).await;
+                if rider_status.session_confirmed
+                    && notification_policy::should_send(
+                        NotificationKind::Discard,
+                        rider,
+                        &rider_status,
+                    )
+                    .await
+                {
+                    discord::send_discard_webhook(rider).await;
+                }
             }
         }
         Status::NotStarted => {
@@ -123,14 +368,20 @@ async fn process_beacon() {
                 tracing::info!(
                     "Beacon data is old and activity never started, clearing beacon url"
                 );
-                db_service::set_beacon_url(None).await;
+                db_service::riders::set_beacon_url(rider.id, None).await;
             }
         }
         Status::UploadedLie => {
             if ride_time > (4 * 60) {
                 tracing::info!("Beacon data indicates activity was uploaded, but no activity id was found. It's been a while, clearing beacon url");
-                db_service::set_troy_status(false).await;
-                discord::send_end_webhook(None).await;
+                db_service::riders::set_beacon_url(rider.id, None).await;
+                end_session(rider, &rider_status).await;
+                if rider_status.session_confirmed
+                    && notification_policy::should_send(NotificationKind::End, rider, &rider_status)
+                        .await
+                {
+                    discord::send_end_webhook(rider, None).await;
+                }
             } else {
                 tracing::info!("Beacon data indicates activity was uploaded, but no activity id found, looping back again");
             }
@@ -139,4 +390,17 @@ async fn process_beacon() {
             tracing::warn!("Beacon data indicates unknown status");
         }
     }
+
+    outcome
+}
+
+// clears out everything tracked for the current ride
+async fn end_session(rider: &Rider, rider_status: &RiderStatus) {
+    db_service::riders::set_on_trail(rider.id, false).await;
+    if rider_status.current_trail_id.is_some() {
+        db_service::riders::set_current_trail(rider.id, None).await;
+    }
+    if rider_status.session_confirmed {
+        db_service::riders::set_session_confirmed(rider.id, false).await;
+    }
 }
@@ -1,17 +1,36 @@
 use serde::ser::SerializeStruct;
 
-use map_service::{DefaultColor, MapImage, TextAlignment, TextOptions};
+use crate::templates::{get_templates, TemplateContext};
+use db_service::riders::Rider;
+use map_service::chart::ProfileData;
+use map_service::colored_route::RouteStyle;
+use map_service::markers;
+use map_service::{MapImage, MapOptions, TextAlignment, TextOptions};
 use shared_lib::strava_structs::Activity;
+use shared_lib::trail_structs::{TrailStatus, TrailSystem};
 
 struct OnTrailsNotification {
+    rider_name: String,
     beacon_url: String,
+    trail_name: Option<String>,
 }
 
 impl From<OnTrailsNotification> for DiscordEmbed {
     fn from(val: OnTrailsNotification) -> Self {
+        let templates = get_templates();
         let mut embed: DiscordEmbed = DiscordEmbed::default();
-        embed.title("Troy is on the trails!");
-        embed.description(&val.beacon_url);
+        let context = TemplateContext::new(&val.rider_name).with("beacon_url", &val.beacon_url);
+        match &val.trail_name {
+            Some(trail_name) => {
+                let context = context.with("trail", trail_name);
+                embed.title(&context.render(&templates.start_at_trail_title));
+                embed.description(&context.render(&templates.start_description));
+            }
+            None => {
+                embed.title(&context.render(&templates.start_title));
+                embed.description(&context.render(&templates.start_description));
+            }
+        };
         embed
     }
 }
@@ -25,45 +44,112 @@ impl From<OnTrailsNotification> for DiscordMessage {
     }
 }
 
+struct TrailChangeNotification {
+    rider_name: String,
+    beacon_url: String,
+    trail_name: String,
+    previous_trail_name: Option<String>,
+}
+
+impl From<TrailChangeNotification> for DiscordEmbed {
+    fn from(val: TrailChangeNotification) -> Self {
+        let templates = get_templates();
+        let mut embed: DiscordEmbed = DiscordEmbed::default();
+        let context = TemplateContext::new(&val.rider_name)
+            .with("beacon_url", &val.beacon_url)
+            .with("trail", &val.trail_name);
+        match &val.previous_trail_name {
+            Some(previous_trail_name) => {
+                let context = context.with("previous_trail", previous_trail_name);
+                embed.title(&context.render(&templates.trail_change_title));
+                embed.description(&context.render(&templates.start_description));
+            }
+            None => {
+                embed.title(&context.render(&templates.arrival_title));
+                embed.description(&context.render(&templates.start_description));
+            }
+        };
+        embed
+    }
+}
+
+impl From<TrailChangeNotification> for DiscordMessage {
+    fn from(val: TrailChangeNotification) -> Self {
+        DiscordMessage {
+            embed: Some(val.into()),
+            ..Default::default()
+        }
+    }
+}
+
 struct OffTrailsNotification {
+    rider_name: String,
     webhook_data: Option<WebhookData>,
 }
 
 struct WebhookData {
+    rider_name: String,
     name: Option<String>,
+    elapsed_time: i64,
     distance: f64,
     total_elevation_gain: f64,
     average_speed: f64,
     max_speed: f64,
     image: Option<WebhookImage>,
 }
-struct WebhookImage(Vec<u8>);
+enum WebhookImage {
+    // a cached ride card, discord fetches it from the site
+    Url(String),
+    Bytes(Vec<u8>),
+}
+
+impl WebhookData {
+    fn template_context(&self) -> TemplateContext {
+        TemplateContext::new(&self.rider_name)
+            .with("name", self.name.clone().unwrap_or_default())
+            .with(
+                "duration",
+                shared_lib::utils::minutes_to_human_readable(self.elapsed_time),
+            )
+            .with("distance", self.distance)
+            .with("elevation_gain", self.total_elevation_gain)
+            .with("average_speed", format!("{:.1}", self.average_speed))
+            .with("top_speed", format!("{:.1}", self.max_speed))
+    }
+}
 
 impl From<OffTrailsNotification> for DiscordEmbed {
     fn from(val: OffTrailsNotification) -> Self {
+        let templates = get_templates();
         let mut embed: DiscordEmbed = DiscordEmbed::default();
 
-        embed.title("Troy is no longer on the trails!");
-
         let webhook_data = &val.webhook_data;
         if webhook_data.is_none() {
+            embed.title(&TemplateContext::new(&val.rider_name).render(&templates.end_title));
             return embed;
         }
         let webhook_data = webhook_data.as_ref().unwrap();
+        let context = webhook_data.template_context();
+
+        embed.title(&context.render(&templates.end_title));
 
         if let Some(image) = &webhook_data.image {
-            embed.image(EmbedImage::Bytes(ByteImageSource {
-                bytes: image.0.clone(),
-                file_name: "map_background.png".to_string(),
-            }));
+            embed.image(match image {
+                WebhookImage::Url(url) => EmbedImage::Url(URLImageSource { url: url.clone() }),
+                WebhookImage::Bytes(bytes) => EmbedImage::Bytes(ByteImageSource {
+                    bytes: bytes.clone(),
+                    file_name: "map_background.png".to_string(),
+                }),
+            });
             tracing::debug!("Image found");
             return embed;
         } else {
             tracing::debug!("No image found");
         }
 
-        if let Some(name) = &webhook_data.name {
-            embed.description = Some(name.to_string());
+        let description = context.render(&templates.end_description);
+        if !description.trim().is_empty() {
+            embed.description = Some(description);
         }
 
         embed
@@ -142,7 +228,7 @@ impl Default for DiscordMessage {
         let avatar_url = &format!("{host_uri}/assets/android-chrome-192x192.png");
 
         let mut message = Self::new();
-        message.username = Some("TOTT".to_string());
+        message.username = Some(get_templates().username.clone());
         message.avatar_url = Some(avatar_url.to_string());
         message
     }
@@ -232,7 +318,7 @@ impl Default for DiscordEmbed {
 
         let mut embed = Self::new();
         embed.footer = Some(EmbedFooter {
-            text: "Powered by troyonthetrails.com".to_string(),
+            text: get_templates().footer.clone(),
             icon_url: avatar_url.to_string(),
         });
 
@@ -291,6 +377,36 @@ pub struct EmbedFooter {
     pub icon_url: String,
 }
 
+struct TrailStatusNotification {
+    trail_name: String,
+    previous_status: String,
+    status: String,
+    status_description: String,
+}
+
+impl From<TrailStatusNotification> for DiscordMessage {
+    fn from(val: TrailStatusNotification) -> Self {
+        let templates = get_templates();
+        let context = TemplateContext::new(&templates.rider_name)
+            .with("trail", &val.trail_name)
+            .with("previous_status", &val.previous_status)
+            .with("status", &val.status)
+            .with("status_description", &val.status_description);
+
+        let mut embed = DiscordEmbed::default();
+        embed.title(&context.render(&templates.trail_status_title));
+        let description = context.render(&templates.trail_status_description);
+        if !description.trim().is_empty() {
+            embed.description(&description);
+        }
+
+        DiscordMessage {
+            embed: Some(embed),
+            ..Default::default()
+        }
+    }
+}
+
 struct StringMessage(String);
 
 impl From<StringMessage> for DiscordMessage {
@@ -303,12 +419,18 @@ impl From<StringMessage> for DiscordMessage {
     }
 }
 
-async fn send_webhook(message: impl Into<DiscordMessage>) {
+// sends to the rider's own webhook url when they have one, otherwise to DISCORD_WEBHOOK_URL
+async fn send_webhook(rider: &Rider, message: impl Into<DiscordMessage>) {
+    post_webhook(rider.discord_webhook_url.clone(), message).await;
+}
+
+// falls back to DISCORD_WEBHOOK_URL when no webhook url is given
+async fn post_webhook(webhook_url: Option<String>, message: impl Into<DiscordMessage>) {
     let message: DiscordMessage = message.into();
 
-    let webhook_url = match std::env::var("DISCORD_WEBHOOK_URL") {
-        Ok(url) => url,
-        Err(_) => {
+    let webhook_url = match webhook_url.or_else(|| std::env::var("DISCORD_WEBHOOK_URL").ok()) {
+        Some(url) => url,
+        None => {
             tracing::debug!("No Discord webhook URL found, skipping");
             return;
         }
@@ -332,11 +454,48 @@ async fn send_webhook(message: impl Into<DiscordMessage>) {
     }
 }
 
-pub async fn send_starting_webhook(beacon_url: String) {
-    send_webhook(OnTrailsNotification { beacon_url }).await;
+pub async fn send_starting_webhook(rider: &Rider, beacon_url: String, trail_name: Option<String>) {
+    send_webhook(
+        rider,
+        OnTrailsNotification {
+            rider_name: rider.name.clone(),
+            beacon_url,
+            trail_name,
+        },
+    )
+    .await;
+}
+
+pub async fn send_trail_change_webhook(
+    rider: &Rider,
+    beacon_url: String,
+    trail_name: String,
+    previous_trail_name: Option<String>,
+) {
+    send_webhook(
+        rider,
+        TrailChangeNotification {
+            rider_name: rider.name.clone(),
+            beacon_url,
+            trail_name,
+            previous_trail_name,
+        },
+    )
+    .await;
 }
 
-pub async fn send_end_webhook(activity_id: Option<i64>) {
+pub async fn send_end_webhook(rider: &Rider, activity_id: Option<i64>) {
+    // the stored strava token can't read other riders' activities, they get the plain end notification
+    let activity_id = match activity_id {
+        Some(_) if !db_service::riders::is_strava_rider(rider.id).await => {
+            tracing::info!(
+                "{} isn't the strava token's owner, skipping the ride details",
+                rider.name
+            );
+            None
+        }
+        activity_id => activity_id,
+    };
     let activity: Option<Activity> = match activity_id {
         Some(activity_id) => match strava_service::get_activity(activity_id).await {
             Ok(activity) => Some(activity),
@@ -355,12 +514,9 @@ pub async fn send_end_webhook(activity_id: Option<i64>) {
             }
 
             Some(activity) => {
-                let name = match activity.name.clone().as_str() {
-                    "Afternoon Mountain Bike Ride" => None,
-                    "Morning Mountain Bike Ride" => None,
-                    "Evening Mountain Bike Ride" => None,
-                    "Lunch Mountain Bike Ride" => None,
-                    _ => Some(activity.name),
+                let name = match get_templates().is_generic_activity_name(&activity.name) {
+                    true => None,
+                    false => Some(activity.name),
                 };
                 let distance = shared_lib::utils::meters_to_miles(activity.distance, false);
                 let total_elevation_gain =
@@ -368,121 +524,208 @@ pub async fn send_end_webhook(activity_id: Option<i64>) {
                 let average_speed = shared_lib::utils::mps_to_miph(activity.average_speed, false);
                 let max_speed = shared_lib::utils::mps_to_miph(activity.max_speed, false);
 
-                let image: Option<WebhookImage> = {
-                    let polyline = match activity.map {
-                        Some(map) => map.summary_polyline,
-                        None => return,
-                    };
-
-                    match get_map_image(
-                        polyline,
-                        &name,
-                        activity.elapsed_time,
-                        distance,
-                        total_elevation_gain,
-                        average_speed,
-                        max_speed,
-                    )
-                    .await
-                    {
-                        Ok(data) => Some(WebhookImage(data)),
-                        Err(e) => {
-                            tracing::error!("Failed to get map image: {:?}", e);
-                            None
-                        }
-                    }
-                };
-
-                Some(WebhookData {
+                let mut webhook_data = WebhookData {
+                    rider_name: rider.name.clone(),
                     name,
+                    elapsed_time: activity.elapsed_time,
                     distance,
                     total_elevation_gain,
                     average_speed,
                     max_speed,
-                    image,
-                })
+                    image: None,
+                };
+
+                let started_at = activity
+                    .start_date
+                    .as_deref()
+                    .and_then(|date| chrono::DateTime::parse_from_rfc3339(date).ok())
+                    .map(|date| date.timestamp());
+                let polyline = match activity.map {
+                    Some(map) => map.summary_polyline,
+                    None => return,
+                };
+
+                webhook_data.image =
+                    get_ride_card(rider, activity.id, started_at, polyline, &webhook_data).await;
+
+                Some(webhook_data)
             }
         }
     };
 
-    send_webhook(OffTrailsNotification { webhook_data }).await;
+    send_webhook(
+        rider,
+        OffTrailsNotification {
+            rider_name: rider.name.clone(),
+            webhook_data,
+        },
+    )
+    .await;
+}
+
+// bump when the card layout changes so cards already in the cache get rendered again
+const RIDE_CARD_VERSION: u32 = 1;
+
+// the ride card from the cache, rendering and caching it when it isn't there yet
+// links to the cached card when the site is reachable by discord, attaches it otherwise
+async fn get_ride_card(
+    rider: &Rider,
+    activity_id: i64,
+    started_at: Option<i64>,
+    polyline: String,
+    webhook_data: &WebhookData,
+) -> Option<WebhookImage> {
+    let variant = format!(
+        "v{}-{}",
+        RIDE_CARD_VERSION,
+        rider.map_theme.as_deref().unwrap_or("default")
+    );
+    let card_url = shared_lib::env_utils::get_public_host_uri()
+        .map(|host| format!("{host}/api/rides/{activity_id}/card.png?v={variant}"));
+
+    if let Some(card) = db_service::ride_cards::get_card(activity_id, Some(&variant)).await {
+        tracing::debug!("Using cached ride card for activity {}", activity_id);
+        return Some(match card_url {
+            Some(url) => WebhookImage::Url(url),
+            None => WebhookImage::Bytes(card.image),
+        });
+    }
+
+    let image = match get_map_image(rider, activity_id, polyline, webhook_data).await {
+        Ok(image) => image,
+        Err(e) => {
+            tracing::error!("Failed to get map image: {:?}", e);
+            return None;
+        }
+    };
+
+    let saved = db_service::ride_cards::save_card(db_service::ride_cards::NewRideCard {
+        activity_id,
+        variant: &variant,
+        rider_id: Some(rider.id),
+        title: webhook_data.name.as_deref(),
+        started_at,
+        content_type: "image/png",
+        image: image.clone(),
+    })
+    .await;
+
+    match (saved, card_url) {
+        (Ok(()), Some(url)) => Some(WebhookImage::Url(url)),
+        (Ok(()), None) => Some(WebhookImage::Bytes(image)),
+        (Err(e), _) => {
+            tracing::error!("Failed to cache ride card: {:?}", e);
+            Some(WebhookImage::Bytes(image))
+        }
+    }
 }
 
 async fn get_map_image(
+    rider: &Rider,
+    activity_id: i64,
     polyline: String,
-    title: &Option<String>,
-    duration: i64,
-    distance: f64,
-    elevation_gain: f64,
-    average_speed: f64,
-    top_speed: f64,
+    webhook_data: &WebhookData,
 ) -> anyhow::Result<Vec<u8>> {
     const TITLE_ROW_HEIGHT: f32 = 50.0;
     const DATA_ROW_HEIGHT: f32 = 36.0;
+    const TRAILHEAD_MARKER_DISTANCE_METERS: f64 = 1500.0;
 
-    let mut map_image = MapImage::new(&polyline)?;
+    let templates = get_templates();
+    let context = webhook_data.template_context();
+
+    let streams = match strava_service::get_activity_streams(activity_id).await {
+        Ok(streams) => Some(streams),
+        Err(e) => {
+            tracing::warn!("Failed to get activity streams: {:?}", e);
+            None
+        }
+    };
+
+    let trails = trail_service::trail_data::get_data().await.trail_data;
+    let map_options = MapOptions {
+        route_style: shared_lib::env_utils::get_map_route_style()
+            .map(|style| RouteStyle::from(style.as_str()))
+            .unwrap_or_default(),
+        streams: streams.clone(),
+        show_start_end: true,
+        markers: markers::nearest_trailheads(
+            &trails,
+            &polyline,
+            TRAILHEAD_MARKER_DISTANCE_METERS,
+            2,
+        ),
+        theme: map_service::theme::get_theme(rider.map_theme.as_deref()),
+        ..Default::default()
+    };
+    let mut map_image = MapImage::with_options(&polyline, map_options).await?;
+
+    match streams.and_then(|streams| ProfileData::from_streams(&streams, false)) {
+        Some(profile) => {
+            map_image.add_elevation_profile(profile);
+        }
+        None => tracing::debug!("No elevation data for activity {}", activity_id),
+    }
 
-    if let Some(title) = &title {
+    if let Some(title) = &webhook_data.name {
         map_image
             .add_text(
                 title.to_uppercase().as_str(),
                 TextOptions {
-                    color: DefaultColor::White,
                     font_size: TITLE_ROW_HEIGHT,
                     alignment: TextAlignment::Center,
+                    ..Default::default()
                 },
             )
             .add_spacer();
     }
 
-    let duration = shared_lib::utils::minutes_to_human_readable(duration);
     map_image
         .add_text(
-            format!("{duration} ride").as_str(),
+            context.render(&templates.duration_line).as_str(),
             TextOptions {
-                color: DefaultColor::White,
                 font_size: DATA_ROW_HEIGHT,
                 alignment: TextAlignment::Center,
+                ..Default::default()
             },
         )
         .add_spacer();
 
     map_image.add_text_with_svg(
-        format!("Rode {distance} miles").as_str(),
+        context.render(&templates.distance_line).as_str(),
         TextOptions {
-            color: DefaultColor::White,
             font_size: DATA_ROW_HEIGHT,
             alignment: TextAlignment::Left,
+            ..Default::default()
         },
         include_bytes!("../assets/measure-2-svgrepo-com.svg"),
     );
 
     map_image.add_text_with_svg(
-        format!("Climbed {elevation_gain} feet").as_str(),
+        context.render(&templates.elevation_line).as_str(),
         TextOptions {
-            color: DefaultColor::White,
             font_size: DATA_ROW_HEIGHT,
             alignment: TextAlignment::Left,
+            ..Default::default()
         },
         include_bytes!("../assets/climb-svgrepo-com.svg"),
     );
 
     map_image.add_text_with_svg(
-        format!("Average speed of {average_speed:.1} mph").as_str(),
+        context.render(&templates.average_speed_line).as_str(),
         TextOptions {
-            color: DefaultColor::White,
             font_size: DATA_ROW_HEIGHT,
             alignment: TextAlignment::Left,
+            ..Default::default()
         },
         include_bytes!("../assets/speedometer-svgrepo-com.svg"),
     );
 
     map_image.add_text_with_svg(
-        format!("Top speed of {top_speed:.1} mph").as_str(),
+        context.render(&templates.top_speed_line).as_str(),
         TextOptions {
-            color: DefaultColor::White,
             font_size: DATA_ROW_HEIGHT,
             alignment: TextAlignment::Left,
+            ..Default::default()
         },
         include_bytes!("../assets/lightning-charge-svgrepo-com.svg"),
     );
@@ -492,9 +735,42 @@ async fn get_map_image(
     Ok(map_image)
 }
 
-pub async fn send_discard_webhook() {
-    send_webhook(StringMessage(
-        "Troy has discarded the Strava activity".to_string(),
-    ))
+pub async fn send_trail_status_webhook(
+    webhook_url: Option<String>,
+    trail: &TrailSystem,
+    previous_status: &TrailStatus,
+) {
+    post_webhook(
+        webhook_url,
+        TrailStatusNotification {
+            trail_name: trail.name.clone(),
+            previous_status: previous_status.to_string(),
+            status: trail.status.to_string(),
+            status_description: trail.status_description.clone(),
+        },
+    )
+    .await;
+}
+
+// error is None once the source is working again
+pub async fn send_trail_data_error_webhook(webhook_url: Option<String>, error: Option<&str>) {
+    let templates = get_templates();
+    let title = match error {
+        Some(error) => TemplateContext::new(&templates.rider_name)
+            .with("error", error)
+            .render(&templates.trail_data_error_title),
+        None => TemplateContext::new(&templates.rider_name)
+            .render(&templates.trail_data_recovered_title),
+    };
+
+    post_webhook(webhook_url, StringMessage(title)).await;
+}
+
+pub async fn send_discard_webhook(rider: &Rider) {
+    let templates = get_templates();
+    send_webhook(
+        rider,
+        StringMessage(TemplateContext::new(&rider.name).render(&templates.discard_title)),
+    )
     .await;
 }
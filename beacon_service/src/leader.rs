@@ -0,0 +1,36 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+// how long a lease is good for without a heartbeat, another instance takes over after this
+const LEASE_TTL: Duration = Duration::from_secs(90);
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+
+static IS_LEADER: AtomicBool = AtomicBool::new(false);
+
+// whether this instance currently holds the beacon lease
+pub fn is_leader() -> bool {
+    IS_LEADER.load(Ordering::Relaxed)
+}
+
+// loop that keeps trying to take (or renew) the db backed lease so exactly one instance runs the beacon loop
+pub fn start() {
+    let owner_id = shared_lib::env_utils::get_instance_id();
+    tracing::info!("Starting beacon leader election as {}", owner_id);
+
+    tokio::spawn(async move {
+        loop {
+            let acquired = db_service::try_acquire_beacon_lease(owner_id, LEASE_TTL).await;
+            let was_leader = IS_LEADER.swap(acquired, Ordering::Relaxed);
+
+            match (was_leader, acquired) {
+                (false, true) => {
+                    tracing::info!("Acquired beacon lease, this instance is the leader")
+                }
+                (true, false) => tracing::warn!("Lost beacon lease to another instance"),
+                _ => tracing::trace!("Beacon lease heartbeat, leader: {}", acquired),
+            }
+
+            tokio::time::sleep(HEARTBEAT_INTERVAL).await;
+        }
+    });
+}
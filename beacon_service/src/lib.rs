@@ -1,4 +1,9 @@
 pub mod beacon_loop;
 pub mod discord;
+pub mod leader;
+pub mod notification_policy;
+pub mod riders;
+pub mod templates;
+pub mod trail_watch;
 
 extern crate strava_service;
@@ -0,0 +1,420 @@
+use std::sync::LazyLock;
+use std::time::SystemTime;
+
+use chrono::{DateTime, FixedOffset, Timelike, Utc};
+use serde::Deserialize;
+
+use db_service::riders::{Rider, RiderStatus};
+
+static POLICY: LazyLock<NotificationPolicy> =
+    LazyLock::new(|| shared_lib::config::load_section("notification_policy"));
+
+pub fn get_policy() -> &'static NotificationPolicy {
+    &POLICY
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NotificationKind {
+    Start,
+    // arriving at the trail system with the given id
+    TrailChange(u64),
+    End,
+    Discard,
+}
+
+impl NotificationKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NotificationKind::Start => "start",
+            NotificationKind::TrailChange(_) => "trail_change",
+            NotificationKind::End => "end",
+            NotificationKind::Discard => "discard",
+        }
+    }
+
+    fn is_enabled_for(&self, rider: &Rider) -> bool {
+        match self {
+            NotificationKind::Start => rider.notify_start,
+            NotificationKind::TrailChange(_) => rider.notify_trail_change,
+            NotificationKind::End | NotificationKind::Discard => rider.notify_end,
+        }
+    }
+
+    fn trail_id(&self) -> Option<u64> {
+        match self {
+            NotificationKind::TrailChange(trail_id) => Some(*trail_id),
+            _ => None,
+        }
+    }
+
+    fn closes_session(&self) -> bool {
+        matches!(self, NotificationKind::End | NotificationKind::Discard)
+    }
+
+    // end and discard both close out a session, so they count as duplicates of each other
+    // trail changes only count as duplicates when they're for the same trail
+    fn is_same_as(&self, other: &str, other_trail_id: Option<u64>) -> bool {
+        match self {
+            NotificationKind::End | NotificationKind::Discard => {
+                other == NotificationKind::End.as_str()
+                    || other == NotificationKind::Discard.as_str()
+            }
+            NotificationKind::TrailChange(trail_id) => {
+                other == self.as_str() && other_trail_id == Some(*trail_id)
+            }
+            _ => other == self.as_str(),
+        }
+    }
+}
+
+// hours are in local time (see utc_offset_hours), start is inclusive and end is exclusive
+// a range like 22 -> 6 wraps around midnight
+#[derive(Debug, Clone, Deserialize)]
+pub struct QuietHours {
+    pub start_hour: u32,
+    pub end_hour: u32,
+}
+
+impl QuietHours {
+    fn contains(&self, hour: u32) -> bool {
+        if self.start_hour <= self.end_hour {
+            (self.start_hour..self.end_hour).contains(&hour)
+        } else {
+            hour >= self.start_hour || hour < self.end_hour
+        }
+    }
+}
+
+// rules for when notifications are allowed to go out, loaded from the `notification_policy` section of the config file
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct NotificationPolicy {
+    // a start notification is held until quiet hours end, if the ride is still going by then
+    // trail change and end notifications during quiet hours are dropped
+    pub quiet_hours: Option<QuietHours>,
+    pub utc_offset_hours: i32,
+    // how long a ride has to be going before the start notification is sent
+    pub min_ride_minutes: i64,
+    // a repeat of the last notification within this window is dropped
+    pub duplicate_window_minutes: u64,
+    // no new notification of any kind goes out this soon after the last one, so a beacon that keeps
+    // stopping and starting doesn't spam the channel, except for ending a session that was announced
+    pub cooldown_minutes: u64,
+}
+
+impl Default for NotificationPolicy {
+    fn default() -> Self {
+        Self {
+            quiet_hours: None,
+            utc_offset_hours: 0,
+            min_ride_minutes: 5,
+            duplicate_window_minutes: 30,
+            cooldown_minutes: 10,
+        }
+    }
+}
+
+impl NotificationPolicy {
+    // whether a ride with the given elapsed time (in seconds) has gone on long enough to announce
+    pub fn is_session_confirmed(&self, elapsed_time: i64) -> bool {
+        elapsed_time >= self.min_ride_minutes * 60
+    }
+
+    // whether the rider's start notification should wait for quiet hours to end before the session is confirmed
+    pub fn should_hold_start(&self, rider: &Rider) -> bool {
+        NotificationKind::Start.is_enabled_for(rider) && self.is_quiet_at(Utc::now())
+    }
+
+    fn is_quiet_at(&self, now: DateTime<Utc>) -> bool {
+        let quiet_hours = match &self.quiet_hours {
+            Some(quiet_hours) => quiet_hours,
+            None => return false,
+        };
+
+        let offset = match FixedOffset::east_opt(self.utc_offset_hours * 3600) {
+            Some(offset) => offset,
+            None => {
+                tracing::warn!("Invalid utc_offset_hours in notification policy, using UTC");
+                FixedOffset::east_opt(0).unwrap()
+            }
+        };
+        let local_hour = now.with_timezone(&offset).hour();
+
+        quiet_hours.contains(local_hour)
+    }
+
+    // the last notification sent to the rider and how long ago it went out
+    fn last_notification(rider_status: &RiderStatus, now: SystemTime) -> Option<(&str, u64)> {
+        let last_notification = rider_status.last_notification.as_deref()?;
+        let elapsed = now
+            .duration_since(rider_status.last_notification_at?)
+            .unwrap_or_default();
+        Some((last_notification, elapsed.as_secs()))
+    }
+
+    fn is_duplicate(
+        &self,
+        kind: NotificationKind,
+        rider_status: &RiderStatus,
+        now: SystemTime,
+    ) -> bool {
+        match Self::last_notification(rider_status, now) {
+            Some((last_notification, elapsed_secs)) => {
+                kind.is_same_as(last_notification, rider_status.last_notification_trail_id)
+                    && elapsed_secs < self.duplicate_window_minutes * 60
+            }
+            None => false,
+        }
+    }
+
+    fn is_cooling_down(
+        &self,
+        kind: NotificationKind,
+        rider_status: &RiderStatus,
+        now: SystemTime,
+    ) -> bool {
+        match Self::last_notification(rider_status, now) {
+            Some((last_notification, elapsed_secs)) => {
+                let ends_announced_session = kind.closes_session()
+                    && !NotificationKind::End.is_same_as(last_notification, None);
+                !ends_announced_session && elapsed_secs < self.cooldown_minutes * 60
+            }
+            None => false,
+        }
+    }
+
+    // for a rider who gets start notifications, an end or discard right after another one means the
+    // session's start never went out, so there's nothing for it to close out
+    fn is_unannounced_end(
+        &self,
+        kind: NotificationKind,
+        rider: &Rider,
+        rider_status: &RiderStatus,
+    ) -> bool {
+        match &rider_status.last_notification {
+            Some(last_notification) => {
+                kind.closes_session()
+                    && NotificationKind::Start.is_enabled_for(rider)
+                    && kind.is_same_as(last_notification, None)
+            }
+            None => false,
+        }
+    }
+}
+
+// checks the notification against the rider's preferences and the policy, recording it as sent if it's allowed through
+pub async fn should_send(
+    kind: NotificationKind,
+    rider: &Rider,
+    rider_status: &RiderStatus,
+) -> bool {
+    let policy = get_policy();
+    let now = SystemTime::now();
+
+    if !kind.is_enabled_for(rider) {
+        tracing::debug!(
+            "{} has {} notifications turned off",
+            rider.name,
+            kind.as_str()
+        );
+        return false;
+    }
+
+    if policy.is_quiet_at(Utc::now()) {
+        tracing::info!("Dropping {} notification during quiet hours", kind.as_str());
+        return false;
+    }
+
+    if policy.is_unannounced_end(kind, rider, rider_status) {
+        tracing::info!(
+            "Suppressing {} notification for a session that was never announced",
+            kind.as_str()
+        );
+        return false;
+    }
+
+    if policy.is_duplicate(kind, rider_status, now) {
+        tracing::info!("Suppressing duplicate {} notification", kind.as_str());
+        return false;
+    }
+
+    if policy.is_cooling_down(kind, rider_status, now) {
+        tracing::info!(
+            "Suppressing {} notification, {} was notified too recently",
+            kind.as_str(),
+            rider.name
+        );
+        return false;
+    }
+
+    db_service::riders::set_last_notification(rider.id, kind.as_str(), kind.trail_id()).await;
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use chrono::{DateTime, Utc};
+    use db_service::riders::{Rider, RiderStatus};
+
+    use super::{NotificationKind, NotificationPolicy, QuietHours};
+
+    fn rider() -> Rider {
+        Rider {
+            id: 1,
+            name: "Troy".to_string(),
+            strava_athlete_id: None,
+            discord_webhook_url: None,
+            notify_start: true,
+            notify_trail_change: true,
+            notify_end: true,
+            map_theme: None,
+        }
+    }
+
+    fn status_after(last_notification: NotificationKind, minutes_ago: u64) -> RiderStatus {
+        RiderStatus {
+            rider_id: 1,
+            is_on_trail: true,
+            beacon_url: None,
+            trail_status_updated: None,
+            current_trail_id: None,
+            session_confirmed: true,
+            last_notification: Some(last_notification.as_str().to_string()),
+            last_notification_at: Some(SystemTime::now() - Duration::from_secs(minutes_ago * 60)),
+            last_notification_trail_id: match last_notification {
+                NotificationKind::TrailChange(trail_id) => Some(trail_id),
+                _ => None,
+            },
+        }
+    }
+
+    // a utc time at the given hour of the day
+    fn at_hour(hour: i64) -> DateTime<Utc> {
+        DateTime::<Utc>::from_timestamp(1_700_006_400 + hour * 3600, 0).unwrap()
+    }
+
+    #[test]
+    fn repeated_notification_is_duplicate() {
+        let policy = NotificationPolicy::default();
+        let now = SystemTime::now();
+        let status = status_after(NotificationKind::Start, 0);
+        assert!(policy.is_duplicate(NotificationKind::Start, &status, now));
+        assert!(!policy.is_duplicate(NotificationKind::End, &status, now));
+    }
+
+    #[test]
+    fn repeat_after_the_duplicate_window_is_not_duplicate() {
+        let policy = NotificationPolicy::default();
+        let status = status_after(NotificationKind::Start, 31);
+        assert!(!policy.is_duplicate(NotificationKind::Start, &status, SystemTime::now()));
+    }
+
+    #[test]
+    fn end_and_discard_are_duplicates() {
+        let policy = NotificationPolicy::default();
+        let status = status_after(NotificationKind::End, 0);
+        assert!(policy.is_duplicate(NotificationKind::Discard, &status, SystemTime::now()));
+    }
+
+    #[test]
+    fn trail_change_to_another_trail_is_not_duplicate() {
+        let policy = NotificationPolicy::default();
+        let now = SystemTime::now();
+        let status = status_after(NotificationKind::TrailChange(1), 0);
+        assert!(policy.is_duplicate(NotificationKind::TrailChange(1), &status, now));
+        assert!(!policy.is_duplicate(NotificationKind::TrailChange(2), &status, now));
+    }
+
+    #[test]
+    fn cooldown_holds_back_any_kind() {
+        let policy = NotificationPolicy::default();
+        let now = SystemTime::now();
+
+        // a beacon that was discarded and started again straight away
+        let status = status_after(NotificationKind::Discard, 2);
+        assert!(policy.is_cooling_down(NotificationKind::Start, &status, now));
+
+        let status = status_after(NotificationKind::Start, 2);
+        assert!(policy.is_cooling_down(NotificationKind::TrailChange(1), &status, now));
+
+        let status = status_after(NotificationKind::Discard, 11);
+        assert!(!policy.is_cooling_down(NotificationKind::Start, &status, now));
+    }
+
+    #[test]
+    fn cooldown_lets_an_announced_session_end() {
+        let policy = NotificationPolicy::default();
+        let now = SystemTime::now();
+        let status = status_after(NotificationKind::Start, 2);
+        assert!(!policy.is_cooling_down(NotificationKind::Discard, &status, now));
+        assert!(!policy.is_cooling_down(NotificationKind::End, &status, now));
+
+        let status = status_after(NotificationKind::TrailChange(1), 2);
+        assert!(!policy.is_cooling_down(NotificationKind::End, &status, now));
+    }
+
+    #[test]
+    fn end_without_an_announced_start_is_suppressed() {
+        let policy = NotificationPolicy::default();
+        let status = status_after(NotificationKind::Discard, 120);
+        assert!(policy.is_unannounced_end(NotificationKind::End, &rider(), &status));
+
+        let status = status_after(NotificationKind::Start, 120);
+        assert!(!policy.is_unannounced_end(NotificationKind::End, &rider(), &status));
+
+        // without start notifications there's never a start to announce
+        let rider = Rider {
+            notify_start: false,
+            ..rider()
+        };
+        let status = status_after(NotificationKind::End, 120);
+        assert!(!policy.is_unannounced_end(NotificationKind::End, &rider, &status));
+    }
+
+    #[test]
+    fn quiet_hours_wrap_around_midnight() {
+        let quiet_hours = QuietHours {
+            start_hour: 22,
+            end_hour: 6,
+        };
+        assert!(quiet_hours.contains(22));
+        assert!(quiet_hours.contains(23));
+        assert!(quiet_hours.contains(0));
+        assert!(quiet_hours.contains(5));
+        assert!(!quiet_hours.contains(6));
+        assert!(!quiet_hours.contains(12));
+        assert!(!quiet_hours.contains(21));
+    }
+
+    #[test]
+    fn quiet_hours_within_a_day() {
+        let quiet_hours = QuietHours {
+            start_hour: 1,
+            end_hour: 5,
+        };
+        assert!(quiet_hours.contains(1));
+        assert!(quiet_hours.contains(4));
+        assert!(!quiet_hours.contains(5));
+        assert!(!quiet_hours.contains(0));
+    }
+
+    #[test]
+    fn quiet_hours_use_the_local_time() {
+        let policy = NotificationPolicy {
+            quiet_hours: Some(QuietHours {
+                start_hour: 22,
+                end_hour: 6,
+            }),
+            utc_offset_hours: -7,
+            ..NotificationPolicy::default()
+        };
+        // 05:00 utc is 22:00 local, 13:00 utc is 06:00 local
+        assert!(policy.is_quiet_at(at_hour(5)));
+        assert!(policy.is_quiet_at(at_hour(12)));
+        assert!(!policy.is_quiet_at(at_hour(13)));
+        assert!(!policy.is_quiet_at(at_hour(4)));
+        assert!(!NotificationPolicy::default().is_quiet_at(at_hour(5)));
+    }
+}
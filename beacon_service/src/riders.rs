@@ -0,0 +1,65 @@
+use serde::Deserialize;
+
+use db_service::riders::RiderRegistration;
+
+// a rider entry from the `riders` section of the config file
+#[derive(Debug, Clone, Deserialize)]
+pub struct RiderConfig {
+    pub name: String,
+    pub strava_athlete_id: Option<i64>,
+    pub discord_webhook_url: Option<String>,
+    #[serde(default = "default_true")]
+    pub notify_start: bool,
+    #[serde(default = "default_true")]
+    pub notify_trail_change: bool,
+    #[serde(default = "default_true")]
+    pub notify_end: bool,
+    // a theme from the `map_themes` section
+    pub map_theme: Option<String>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl From<RiderConfig> for RiderRegistration {
+    fn from(val: RiderConfig) -> Self {
+        RiderRegistration {
+            name: val.name,
+            strava_athlete_id: val.strava_athlete_id,
+            discord_webhook_url: val.discord_webhook_url,
+            notify_start: val.notify_start,
+            notify_trail_change: val.notify_trail_change,
+            notify_end: val.notify_end,
+            map_theme: val.map_theme,
+        }
+    }
+}
+
+// registers the configured riders in the db
+// without any configured riders the default rider (named in the notification templates) is registered
+pub async fn sync_from_config() {
+    let mut riders: Vec<RiderConfig> = shared_lib::config::load_section("riders");
+
+    if riders.is_empty() {
+        riders.push(RiderConfig {
+            name: crate::templates::get_templates().rider_name.clone(),
+            strava_athlete_id: shared_lib::env_utils::get_strava_user_id()
+                .and_then(|id| id.parse().ok()),
+            discord_webhook_url: None,
+            notify_start: true,
+            notify_trail_change: true,
+            notify_end: true,
+            map_theme: None,
+        });
+    }
+
+    for rider in riders {
+        db_service::riders::upsert_rider(rider.into()).await;
+    }
+
+    // the old single rider status belonged to the strava token's owner
+    if let Some(rider) = db_service::riders::get_strava_rider().await {
+        db_service::riders::carry_over_legacy_status(rider.id).await;
+    }
+}
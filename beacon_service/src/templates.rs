@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+use serde::Deserialize;
+
+static TEMPLATES: LazyLock<NotificationTemplates> =
+    LazyLock::new(|| shared_lib::config::load_section("notifications"));
+
+pub fn get_templates() -> &'static NotificationTemplates {
+    &TEMPLATES
+}
+
+// message templates for the notifications, loaded from the `notifications` section of the config file
+// placeholders like {rider} or {trail} are filled in by `TemplateContext::render`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct NotificationTemplates {
+    pub username: String,
+    // name of the rider registered when no riders are configured
+    pub rider_name: String,
+    pub footer: String,
+    pub start_title: String,
+    pub start_at_trail_title: String,
+    pub start_description: String,
+    pub arrival_title: String,
+    pub trail_change_title: String,
+    pub end_title: String,
+    pub end_description: String,
+    pub discard_title: String,
+    pub duration_line: String,
+    pub distance_line: String,
+    pub elevation_line: String,
+    pub average_speed_line: String,
+    pub top_speed_line: String,
+    // sent when a watched trail system's status changes, {rider} is the default rider name here
+    pub trail_status_title: String,
+    pub trail_status_description: String,
+    // sent when the trail data source stops working, and when it's working again
+    pub trail_data_error_title: String,
+    pub trail_data_recovered_title: String,
+    // activity names that strava generates automatically, these aren't worth showing
+    pub generic_activity_names: Vec<String>,
+}
+
+impl Default for NotificationTemplates {
+    fn default() -> Self {
+        Self {
+            username: "TOTT".to_string(),
+            rider_name: "Troy".to_string(),
+            footer: "Powered by troyonthetrails.com".to_string(),
+            start_title: "{rider} is on the trails!".to_string(),
+            start_at_trail_title: "{rider} is on the trails at {trail}!".to_string(),
+            start_description: "{beacon_url}".to_string(),
+            arrival_title: "{rider} has arrived at {trail}!".to_string(),
+            trail_change_title: "{rider} moved from {previous_trail} to {trail}!".to_string(),
+            end_title: "{rider} is no longer on the trails!".to_string(),
+            end_description: "{name}".to_string(),
+            discard_title: "{rider} has discarded the Strava activity".to_string(),
+            duration_line: "{duration} ride".to_string(),
+            distance_line: "Rode {distance} miles".to_string(),
+            elevation_line: "Climbed {elevation_gain} feet".to_string(),
+            average_speed_line: "Average speed of {average_speed} mph".to_string(),
+            top_speed_line: "Top speed of {top_speed} mph".to_string(),
+            trail_status_title: "{trail} went from {previous_status} to {status}".to_string(),
+            trail_status_description: "{status_description}".to_string(),
+            trail_data_error_title: "Trail data isn't updating: {error}".to_string(),
+            trail_data_recovered_title: "Trail data is updating again".to_string(),
+            generic_activity_names: vec![
+                "Afternoon Mountain Bike Ride".to_string(),
+                "Morning Mountain Bike Ride".to_string(),
+                "Evening Mountain Bike Ride".to_string(),
+                "Lunch Mountain Bike Ride".to_string(),
+            ],
+        }
+    }
+}
+
+impl NotificationTemplates {
+    pub fn is_generic_activity_name(&self, name: &str) -> bool {
+        self.generic_activity_names
+            .iter()
+            .any(|generic| generic == name)
+    }
+}
+
+// values available to the templates, always includes {rider}
+pub struct TemplateContext {
+    values: HashMap<&'static str, String>,
+}
+
+impl TemplateContext {
+    pub fn new(rider_name: &str) -> Self {
+        let mut values = HashMap::new();
+        values.insert("rider", rider_name.to_string());
+        TemplateContext { values }
+    }
+
+    pub fn with(mut self, key: &'static str, value: impl ToString) -> Self {
+        self.values.insert(key, value.to_string());
+        self
+    }
+
+    // replaces every known {placeholder} in the template, unknown placeholders are left as-is
+    // a single pass over the template, so placeholders inside the values (e.g. a ride name) are never replaced
+    pub fn render(&self, template: &str) -> String {
+        let mut rendered = String::with_capacity(template.len());
+        let mut rest = template;
+
+        while let Some(start) = rest.find('{') {
+            rendered.push_str(&rest[..start]);
+            let after = &rest[start + 1..];
+
+            let value = after
+                .find('}')
+                .and_then(|end| Some((self.values.get(&after[..end])?, end)));
+            match value {
+                Some((value, end)) => {
+                    rendered.push_str(value);
+                    rest = &after[end + 1..];
+                }
+                None => {
+                    rendered.push('{');
+                    rest = after;
+                }
+            }
+        }
+
+        rendered.push_str(rest);
+        rendered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TemplateContext;
+
+    #[test]
+    fn render_replaces_known_placeholders() {
+        let context = TemplateContext::new("Troy").with("trail", "Walnut Creek");
+        assert_eq!(
+            context.render("{rider} is at {trail}"),
+            "Troy is at Walnut Creek"
+        );
+    }
+
+    #[test]
+    fn render_leaves_unknown_placeholders() {
+        let context = TemplateContext::new("Troy");
+        assert_eq!(
+            context.render("{rider} {unknown} {{rider} {rider"),
+            "Troy {unknown} {Troy {rider"
+        );
+    }
+
+    #[test]
+    fn render_leaves_placeholders_in_values() {
+        let context = TemplateContext::new("Troy")
+            .with("name", "{rider} rides {trail}")
+            .with("trail", "Walnut Creek");
+        assert_eq!(
+            context.render("{name} at {trail}"),
+            "{rider} rides {trail} at Walnut Creek"
+        );
+    }
+}
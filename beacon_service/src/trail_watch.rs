@@ -0,0 +1,131 @@
+use std::time::Duration;
+
+use serde::Deserialize;
+use shared_lib::trail_structs::TrailStatus;
+
+use crate::discord;
+use crate::leader;
+
+// the trail data is cached for 5 minutes, checking more often wouldn't see anything new
+const CHECK_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+// the `trail_watch` section of the config file
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+struct TrailWatchConfig {
+    // ids of the trail systems to send alerts for
+    trails: Vec<u64>,
+    // where the alerts go, DISCORD_WEBHOOK_URL when not set
+    discord_webhook_url: Option<String>,
+}
+
+// loop that records the status history of every trail system and sends an alert when a watched one changes,
+// or when the trail data source stops working
+// only the instance holding the beacon lease checks, so changes aren't recorded or sent twice
+pub fn start() {
+    let config: TrailWatchConfig = shared_lib::config::load_section("trail_watch");
+    tracing::info!(
+        "Watching {} trail systems for status changes",
+        config.trails.len()
+    );
+
+    tokio::spawn(async move {
+        // the last trail data error, so an alert goes out when the source breaks and not on every check
+        let mut source_error: Option<String> = None;
+        loop {
+            if leader::is_leader() {
+                check_trails(&config, &mut source_error).await;
+            } else {
+                tracing::trace!("Not the beacon leader, skipping trail status check");
+            }
+
+            tokio::time::sleep(CHECK_INTERVAL).await;
+        }
+    });
+}
+
+async fn check_trails(config: &TrailWatchConfig, source_error: &mut Option<String>) {
+    let trail_data = trail_service::trail_data::get_data().await;
+    if trail_data.error.is_some() != source_error.is_some() {
+        match &trail_data.error {
+            Some(error) => tracing::warn!("Trail data source broke: {}", error),
+            None => tracing::info!("Trail data source is working again"),
+        }
+        discord::send_trail_data_error_webhook(
+            config.discord_webhook_url.clone(),
+            trail_data.error.as_deref(),
+        )
+        .await;
+    }
+    *source_error = trail_data.error;
+
+    // statuses in a stale cache aren't changes
+    if source_error.is_some() {
+        return;
+    }
+
+    let trails = trail_data.trail_data;
+    if trails.is_empty() {
+        tracing::warn!("No trail data, skipping trail status check");
+        return;
+    }
+
+    let latest_statuses = db_service::trail_status::get_latest_statuses().await;
+
+    for trail in &trails {
+        // a status the data source doesn't recognize is treated as no data, not a change
+        if trail.status == TrailStatus::Unknown {
+            continue;
+        }
+
+        let latest = latest_statuses.get(&trail.id);
+        let previous_status = latest.map(|latest| &latest.status);
+        let description_changed = latest.is_some_and(|latest| {
+            latest.status_description.as_deref() != Some(trail.status_description.as_str())
+        });
+        if previous_status == Some(&trail.status) && !description_changed {
+            continue;
+        }
+
+        if let Err(e) = db_service::trail_status::record_status_change(
+            trail.id,
+            previous_status,
+            &trail.status,
+            &trail.status_description,
+        )
+        .await
+        {
+            tracing::error!("Failed to record status change for {}: {:?}", trail.name, e);
+            continue;
+        }
+
+        match previous_status {
+            // only the description changed
+            Some(previous_status) if *previous_status == trail.status => {
+                tracing::debug!("{} status description changed", trail.name)
+            }
+            Some(previous_status) if config.trails.contains(&trail.id) => {
+                tracing::info!(
+                    "{} went from {} to {}",
+                    trail.name,
+                    previous_status,
+                    trail.status
+                );
+                discord::send_trail_status_webhook(
+                    config.discord_webhook_url.clone(),
+                    trail,
+                    previous_status,
+                )
+                .await;
+            }
+            Some(previous_status) => tracing::debug!(
+                "{} went from {} to {}",
+                trail.name,
+                previous_status,
+                trail.status
+            ),
+            // first time seeing the trail, nothing to compare against yet
+            None => tracing::debug!("Recorded first status for {}: {}", trail.name, trail.status),
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+
+use chrono::{DateTime, Utc};
+use shared_lib::track_export::TrackPoint;
+
+use crate::{unix_timestamp, DBTable, DB_SERVICE};
+
+// points are inserted in batches so a long ride doesn't turn into a huge statement
+const INSERT_BATCH_SIZE: usize = 200;
+// live tracks are only kept around for exports shortly after the ride
+const TRACK_RETENTION_SECS: i64 = 7 * 24 * 60 * 60;
+
+// the newest stored point of each live activity, so a poll only writes the points that are new
+static LAST_RECORDED_AT: LazyLock<Mutex<HashMap<i64, i64>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+#[derive(Debug, serde::Deserialize, Clone)]
+struct LastRecordedAtRow {
+    recorded_at: Option<i64>,
+}
+
+#[derive(Debug, serde::Deserialize, Clone)]
+struct BeaconTrackRow {
+    recorded_at: i64,
+    lat: f64,
+    lng: f64,
+}
+
+// a breadcrumb from a live beacon, recorded_at is a unix timestamp in seconds
+#[derive(Debug, Clone, Copy)]
+pub struct BeaconPoint {
+    pub recorded_at: i64,
+    pub lat: f64,
+    pub lng: f64,
+}
+
+// every poll returns the whole track so far, only the points after the newest stored one are written
+pub async fn add_points(rider_id: i64, live_activity_id: i64, points: &[BeaconPoint]) {
+    if points.is_empty() {
+        return;
+    }
+
+    let cached = LAST_RECORDED_AT
+        .lock()
+        .unwrap()
+        .get(&live_activity_id)
+        .copied();
+    let last_recorded_at = match cached {
+        Some(recorded_at) => Some(recorded_at),
+        // a new ride or a restart, a good time to clear out old tracks too
+        None => {
+            prune_old_tracks().await;
+            get_last_recorded_at(live_activity_id).await
+        }
+    };
+
+    let new_points: Vec<BeaconPoint> = points
+        .iter()
+        .filter(|point| last_recorded_at.is_none_or(|last| point.recorded_at > last))
+        .copied()
+        .collect();
+    if new_points.is_empty() {
+        return;
+    }
+
+    for batch in new_points.chunks(INSERT_BATCH_SIZE) {
+        let placeholders = vec!["(?, ?, ?, ?, ?)"; batch.len()].join(", ");
+        let params: Vec<libsql::Value> = batch
+            .iter()
+            .flat_map(|point| {
+                [
+                    live_activity_id.into(),
+                    rider_id.into(),
+                    point.recorded_at.into(),
+                    point.lat.into(),
+                    point.lng.into(),
+                ]
+            })
+            .collect();
+
+        let result = DB_SERVICE
+            .get()
+            .unwrap()
+            .try_execute(
+                &format!(
+                    "INSERT OR IGNORE INTO beacon_tracks (live_activity_id, rider_id, recorded_at, lat, lng) VALUES {placeholders}"
+                ),
+                libsql::params_from_iter(params),
+                DBTable::BeaconTracks,
+            )
+            .await;
+
+        if let Err(e) = result {
+            tracing::error!("Failed to save beacon track points: {:?}", e);
+            return;
+        }
+
+        if let Some(newest) = batch.iter().map(|point| point.recorded_at).max() {
+            let mut last_recorded_at = LAST_RECORDED_AT.lock().unwrap();
+            let last = last_recorded_at.entry(live_activity_id).or_insert(newest);
+            *last = (*last).max(newest);
+        }
+    }
+}
+
+async fn get_last_recorded_at(live_activity_id: i64) -> Option<i64> {
+    let result = DB_SERVICE
+        .get()
+        .unwrap()
+        .query_many::<LastRecordedAtRow>(
+            "SELECT MAX(recorded_at) AS recorded_at FROM beacon_tracks WHERE live_activity_id = ?",
+            libsql::params!(live_activity_id),
+        )
+        .await;
+
+    match result {
+        Ok(rows) => rows.into_iter().next().and_then(|row| row.recorded_at),
+        Err(e) => {
+            tracing::error!("Failed to get the last beacon track point: {:?}", e);
+            None
+        }
+    }
+}
+
+async fn prune_old_tracks() {
+    let cutoff = unix_timestamp() - TRACK_RETENTION_SECS;
+    let result = DB_SERVICE
+        .get()
+        .unwrap()
+        .try_execute(
+            "DELETE FROM beacon_tracks WHERE live_activity_id IN \
+                (SELECT live_activity_id FROM beacon_tracks GROUP BY live_activity_id HAVING MAX(recorded_at) < ?)",
+            libsql::params!(cutoff),
+            DBTable::BeaconTracks,
+        )
+        .await;
+
+    match result {
+        Ok(0) => {}
+        Ok(count) => tracing::debug!("Pruned {} old beacon track points", count),
+        Err(e) => tracing::warn!("Failed to prune old beacon tracks: {:?}", e),
+    }
+
+    // rides that were pruned won't be polled again
+    LAST_RECORDED_AT
+        .lock()
+        .unwrap()
+        .retain(|_, recorded_at| *recorded_at >= cutoff);
+}
+
+pub async fn get_track(live_activity_id: i64) -> Vec<TrackPoint> {
+    let result = DB_SERVICE
+        .get()
+        .unwrap()
+        .query_many::<BeaconTrackRow>(
+            "SELECT recorded_at, lat, lng FROM beacon_tracks WHERE live_activity_id = ? ORDER BY recorded_at",
+            libsql::params!(live_activity_id),
+        )
+        .await;
+
+    match result {
+        Ok(rows) => rows
+            .into_iter()
+            .map(|row| TrackPoint {
+                lat: row.lat,
+                lng: row.lng,
+                elevation: None,
+                time: DateTime::<Utc>::from_timestamp(row.recorded_at, 0),
+            })
+            .collect(),
+        Err(e) => {
+            tracing::error!("Failed to get beacon track from the DB: {:?}", e);
+            Vec::new()
+        }
+    }
+}
@@ -1,4 +1,9 @@
+pub mod beacon_tracks;
 mod encryption;
+pub mod ride_cards;
+pub mod riders;
+pub mod trail_status;
+pub mod webhook_signatures;
 
 use std::{
     env,
@@ -16,27 +21,40 @@ use shared_lib::strava_structs::TokenData;
 
 static DB_SERVICE: OnceCell<DbService> = OnceCell::const_new();
 
-#[derive(Debug)]
-pub struct TroyStatus {
-    pub is_on_trail: bool,
-    pub beacon_url: Option<String>,
-    pub trail_status_updated: Option<SystemTime>,
-}
-
 pub enum DBTable {
-    TroyStatus,
+    Riders,
+    RiderStatus,
     StravaAuth,
+    BeaconLease,
+    BeaconTracks,
+    RideCards,
+    TrailStatusHistory,
+    WebhookSignatures,
 }
 
 impl Display for DBTable {
     fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
         match self {
-            DBTable::TroyStatus => write!(f, "troy_status"),
+            DBTable::Riders => write!(f, "riders"),
+            DBTable::RiderStatus => write!(f, "rider_status"),
             DBTable::StravaAuth => write!(f, "strava_auth"),
+            DBTable::BeaconLease => write!(f, "beacon_lease"),
+            DBTable::BeaconTracks => write!(f, "beacon_tracks"),
+            DBTable::RideCards => write!(f, "ride_cards"),
+            DBTable::TrailStatusHistory => write!(f, "trail_status_history"),
+            DBTable::WebhookSignatures => write!(f, "webhook_signatures"),
         }
     }
 }
 
+// current unix timestamp in seconds
+pub(crate) fn unix_timestamp() -> i64 {
+    match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs() as i64,
+        Err(_) => 0,
+    }
+}
+
 pub async fn get_db_service() -> &'static DbService {
     DB_SERVICE
         .get_or_init(|| async {
@@ -85,6 +103,54 @@ impl DbService {
                 libsql::params!(),
             )
             .await;
+        let _ = conn
+            .execute(
+                "CREATE TABLE IF NOT EXISTS riders (id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT NOT NULL UNIQUE, strava_athlete_id INTEGER, discord_webhook_url TEXT, notify_start INTEGER, notify_trail_change INTEGER, notify_end INTEGER, map_theme TEXT)",
+                libsql::params!(),
+            )
+            .await;
+        let _ = conn
+            .execute(
+                "CREATE TABLE IF NOT EXISTS rider_status (rider_id INTEGER PRIMARY KEY, is_on_trail INTEGER, beacon_url TEXT, trail_status_updated INTEGER, current_trail_id INTEGER, session_confirmed INTEGER, last_notification TEXT, last_notification_at INTEGER, last_notification_trail_id INTEGER, beacon_url_updated_at INTEGER)",
+                libsql::params!(),
+            )
+            .await;
+        let _ = conn
+            .execute(
+                "CREATE TABLE IF NOT EXISTS beacon_lease (id INTEGER PRIMARY KEY CHECK (id = 1), owner_id TEXT, expires_at INTEGER, heartbeat_at INTEGER)",
+                libsql::params!(),
+            )
+            .await;
+        let _ = conn
+            .execute(
+                "CREATE TABLE IF NOT EXISTS beacon_tracks (live_activity_id INTEGER NOT NULL, rider_id INTEGER NOT NULL, recorded_at INTEGER NOT NULL, lat REAL NOT NULL, lng REAL NOT NULL, PRIMARY KEY (live_activity_id, recorded_at))",
+                libsql::params!(),
+            )
+            .await;
+        let _ = conn
+            .execute(
+                "CREATE TABLE IF NOT EXISTS ride_cards (activity_id INTEGER NOT NULL, variant TEXT NOT NULL, rider_id INTEGER, title TEXT, content_type TEXT NOT NULL, image BLOB NOT NULL, created_at INTEGER NOT NULL, started_at INTEGER, PRIMARY KEY (activity_id, variant))",
+                libsql::params!(),
+            )
+            .await;
+        let _ = conn
+            .execute(
+                "CREATE TABLE IF NOT EXISTS trail_status_history (id INTEGER PRIMARY KEY AUTOINCREMENT, trail_id INTEGER NOT NULL, status TEXT NOT NULL, previous_status TEXT, status_description TEXT, changed_at INTEGER NOT NULL)",
+                libsql::params!(),
+            )
+            .await;
+        let _ = conn
+            .execute(
+                "CREATE TABLE IF NOT EXISTS webhook_signatures (signature TEXT PRIMARY KEY, expires_at INTEGER NOT NULL)",
+                libsql::params!(),
+            )
+            .await;
+        let _ = conn
+            .execute(
+                "ALTER TABLE trail_status_history ADD COLUMN status_description TEXT",
+                libsql::params!(),
+            )
+            .await;
 
         let _ = conn
             .execute(
@@ -92,6 +158,55 @@ impl DbService {
                 libsql::params!(),
             )
             .await;
+        let _ = conn
+            .execute(
+                "ALTER TABLE troy_status ADD COLUMN current_trail_id INTEGER",
+                libsql::params!(),
+            )
+            .await;
+        let _ = conn
+            .execute(
+                "ALTER TABLE troy_status ADD COLUMN session_confirmed INTEGER",
+                libsql::params!(),
+            )
+            .await;
+        let _ = conn
+            .execute(
+                "ALTER TABLE troy_status ADD COLUMN last_notification TEXT",
+                libsql::params!(),
+            )
+            .await;
+        let _ = conn
+            .execute(
+                "ALTER TABLE troy_status ADD COLUMN last_notification_at INTEGER",
+                libsql::params!(),
+            )
+            .await;
+
+        let _ = conn
+            .execute(
+                "ALTER TABLE riders ADD COLUMN map_theme TEXT",
+                libsql::params!(),
+            )
+            .await;
+        let _ = conn
+            .execute(
+                "ALTER TABLE ride_cards ADD COLUMN started_at INTEGER",
+                libsql::params!(),
+            )
+            .await;
+        let _ = conn
+            .execute(
+                "ALTER TABLE rider_status ADD COLUMN last_notification_trail_id INTEGER",
+                libsql::params!(),
+            )
+            .await;
+        let _ = conn
+            .execute(
+                "ALTER TABLE rider_status ADD COLUMN beacon_url_updated_at INTEGER",
+                libsql::params!(),
+            )
+            .await;
     }
 
     // execute the statement and return the number of rows affected
@@ -127,6 +242,30 @@ impl DbService {
         Ok(result)
     }
 
+    // execute the statement and return the number of rows affected, for statements where
+    // zero affected rows is an expected outcome rather than a failure
+    // also syncs the DB with remote primary
+    pub async fn try_execute(
+        &self,
+        statement: &str,
+        params: impl IntoParams,
+        table: DBTable,
+    ) -> anyhow::Result<u64> {
+        let db = &self.db;
+        let result = db.connect()?.execute(statement, params).await?;
+
+        tracing::trace!("{} rows affected in {}", result, table);
+
+        let _sync = db.sync().await?;
+        Ok(result)
+    }
+
+    // pulls in writes made through other instances since the last sync
+    pub async fn sync(&self) -> anyhow::Result<()> {
+        self.db.sync().await?;
+        Ok(())
+    }
+
     pub async fn query_many<T>(
         &self,
         statement: &str,
@@ -176,83 +315,35 @@ impl DbService {
     }
 }
 
-pub async fn get_troy_status() -> TroyStatus {
-    #[derive(Debug, serde::Deserialize, Clone)]
-    #[allow(dead_code)]
-    struct TroyStatusRow {
-        id: i64,
-        is_on_trail: u8,
-        beacon_url: Option<String>,
-        trail_status_updated: u64,
-    }
+// takes (or renews) the beacon loop lease for the given owner
+// only succeeds if the lease is unclaimed, already held by the owner, or expired
+pub async fn try_acquire_beacon_lease(owner_id: &str, ttl: Duration) -> bool {
+    let current_timestamp = unix_timestamp();
+    let expires_at = current_timestamp + ttl.as_secs() as i64;
 
-    let db_service = DB_SERVICE.get().unwrap();
-    let result = db_service
-        .query_one::<TroyStatusRow>("SELECT * FROM troy_status", libsql::params!())
+    let result = DB_SERVICE
+        .get()
+        .unwrap()
+        .try_execute(
+            "INSERT INTO beacon_lease (id, owner_id, expires_at, heartbeat_at) \
+                VALUES (1, ?, ?, ?) \
+                ON CONFLICT (id) \
+                DO UPDATE SET owner_id = excluded.owner_id, expires_at = excluded.expires_at, heartbeat_at = excluded.heartbeat_at \
+                WHERE beacon_lease.owner_id = excluded.owner_id OR beacon_lease.expires_at < excluded.heartbeat_at",
+            libsql::params!(owner_id, expires_at, current_timestamp),
+            DBTable::BeaconLease,
+        )
         .await;
 
     match result {
-        Ok(result) => {
-            tracing::trace!("Retrieved troy status from the DB: {:?}", result);
-            TroyStatus {
-                is_on_trail: result.is_on_trail == 1,
-                beacon_url: result.beacon_url.clone(),
-                trail_status_updated: Some(
-                    SystemTime::UNIX_EPOCH + Duration::from_secs(result.trail_status_updated),
-                ),
-            }
-        }
+        Ok(rows_affected) => rows_affected > 0,
         Err(e) => {
-            tracing::error!("Failed to get troy status from the DB: {:?}", e);
-            TroyStatus {
-                is_on_trail: false,
-                beacon_url: None,
-                trail_status_updated: None,
-            }
+            tracing::error!("Failed to acquire beacon lease: {:?}", e);
+            false
         }
     }
 }
 
-pub async fn set_troy_status(is_on_trail: bool) {
-    let is_on_trail = match is_on_trail {
-        true => 1,
-        false => 0,
-    };
-
-    // get current unix milis timestamp
-    let current_timestamp: i64 = match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
-        Ok(duration) => duration.as_secs() as i64,
-        Err(_) => 0,
-    };
-
-    tracing::debug!("Updating troy status in the DB to {}", is_on_trail);
-
-    let _ = DB_SERVICE.get().unwrap()
-            .execute(
-                "INSERT INTO troy_status (id, is_on_trail, trail_status_updated) \
-                VALUES (1, ?, ?) \
-                ON CONFLICT (id) \
-                DO UPDATE SET is_on_trail = excluded.is_on_trail, trail_status_updated = excluded.trail_status_updated",
-                libsql::params!(is_on_trail, current_timestamp),
-                DBTable::TroyStatus).await;
-}
-
-pub async fn set_beacon_url(beacon_url: Option<String>) {
-    tracing::debug!("Updating beacon url in the DB to {:?}", beacon_url);
-    let _ = DB_SERVICE
-        .get()
-        .unwrap()
-        .execute(
-            "INSERT INTO troy_status (id, beacon_url) \
-                VALUES (1, ?) \
-                ON CONFLICT (id) \
-                DO UPDATE SET beacon_url = excluded.beacon_url",
-            libsql::params!(beacon_url),
-            DBTable::TroyStatus,
-        )
-        .await;
-}
-
 pub async fn get_strava_auth() -> anyhow::Result<TokenData> {
     #[derive(Debug, serde::Deserialize, Clone)]
     #[allow(dead_code)]
@@ -0,0 +1,130 @@
+use crate::{unix_timestamp, DBTable, DB_SERVICE};
+
+// cards are kept for this many of the most recent activities, older ones are deleted as new cards come in
+const MAX_CARD_ACTIVITIES: i64 = 100;
+
+// a rendered ride card image, the variant covers the card layout version and theme so a change to either
+// renders a new card, which replaces the activity's previous one
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct RideCard {
+    pub activity_id: i64,
+    pub variant: String,
+    pub rider_id: Option<i64>,
+    pub title: Option<String>,
+    pub content_type: String,
+    pub image: Vec<u8>,
+    pub created_at: i64,
+    // when the ride started, unix timestamp in seconds
+    pub started_at: Option<i64>,
+}
+
+// a card without the image, for listing past rides
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct RideCardSummary {
+    pub activity_id: i64,
+    pub variant: String,
+    pub rider_id: Option<i64>,
+    pub rider_name: Option<String>,
+    pub title: Option<String>,
+    pub created_at: i64,
+    pub started_at: Option<i64>,
+}
+
+pub struct NewRideCard<'a> {
+    pub activity_id: i64,
+    pub variant: &'a str,
+    pub rider_id: Option<i64>,
+    pub title: Option<&'a str>,
+    pub started_at: Option<i64>,
+    pub content_type: &'a str,
+    pub image: Vec<u8>,
+}
+
+// saves the card, replacing the activity's other variants and dropping cards of old activities
+pub async fn save_card(card: NewRideCard<'_>) -> anyhow::Result<()> {
+    let db_service = DB_SERVICE.get().unwrap();
+    db_service
+        .execute(
+            "INSERT OR REPLACE INTO ride_cards (activity_id, variant, rider_id, title, content_type, image, created_at, started_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+            libsql::params!(
+                card.activity_id,
+                card.variant,
+                card.rider_id,
+                card.title,
+                card.content_type,
+                card.image,
+                unix_timestamp(),
+                card.started_at
+            ),
+            DBTable::RideCards,
+        )
+        .await?;
+
+    let pruned = db_service
+        .try_execute(
+            "DELETE FROM ride_cards WHERE (activity_id = ? AND variant != ?) \
+                OR activity_id NOT IN (SELECT DISTINCT activity_id FROM ride_cards ORDER BY activity_id DESC LIMIT ?)",
+            libsql::params!(card.activity_id, card.variant, MAX_CARD_ACTIVITIES),
+            DBTable::RideCards,
+        )
+        .await;
+    match pruned {
+        Ok(0) => {}
+        Ok(count) => tracing::debug!("Pruned {} old ride cards", count),
+        Err(e) => tracing::warn!("Failed to prune old ride cards: {:?}", e),
+    }
+
+    Ok(())
+}
+
+// the card for the given variant, or the newest card for the activity when no variant is given
+pub async fn get_card(activity_id: i64, variant: Option<&str>) -> Option<RideCard> {
+    let db_service = DB_SERVICE.get().unwrap();
+    let result = match variant {
+        Some(variant) => {
+            db_service
+                .query_many::<RideCard>(
+                    "SELECT * FROM ride_cards WHERE activity_id = ? AND variant = ?",
+                    libsql::params!(activity_id, variant),
+                )
+                .await
+        }
+        None => db_service
+            .query_many::<RideCard>(
+                "SELECT * FROM ride_cards WHERE activity_id = ? ORDER BY created_at DESC LIMIT 1",
+                libsql::params!(activity_id),
+            )
+            .await,
+    };
+
+    match result {
+        Ok(cards) => cards.into_iter().next(),
+        Err(e) => {
+            tracing::error!("Failed to get ride card from the DB: {:?}", e);
+            None
+        }
+    }
+}
+
+// the newest card of each activity, most recent rides first
+pub async fn get_recent_cards(limit: i64) -> Vec<RideCardSummary> {
+    // sqlite takes the bare columns from the row that has the MAX
+    let result = DB_SERVICE
+        .get()
+        .unwrap()
+        .query_many::<RideCardSummary>(
+            "SELECT ride_cards.activity_id, ride_cards.variant, ride_cards.rider_id, riders.name AS rider_name, ride_cards.title, MAX(ride_cards.created_at) AS created_at, ride_cards.started_at \
+                FROM ride_cards LEFT JOIN riders ON riders.id = ride_cards.rider_id \
+                GROUP BY ride_cards.activity_id ORDER BY ride_cards.activity_id DESC LIMIT ?",
+            libsql::params!(limit),
+        )
+        .await;
+
+    match result {
+        Ok(cards) => cards,
+        Err(e) => {
+            tracing::error!("Failed to get ride cards from the DB: {:?}", e);
+            Vec::new()
+        }
+    }
+}
@@ -0,0 +1,404 @@
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+use crate::{unix_timestamp, DBTable, DB_SERVICE};
+
+#[derive(Debug, Clone)]
+pub struct Rider {
+    pub id: i64,
+    pub name: String,
+    pub strava_athlete_id: Option<i64>,
+    // overrides DISCORD_WEBHOOK_URL for this rider's notifications
+    pub discord_webhook_url: Option<String>,
+    pub notify_start: bool,
+    pub notify_trail_change: bool,
+    pub notify_end: bool,
+    // the map theme for this rider's notification images
+    pub map_theme: Option<String>,
+}
+
+// what a rider gets registered with, the name is the unique key
+#[derive(Debug, Clone)]
+pub struct RiderRegistration {
+    pub name: String,
+    pub strava_athlete_id: Option<i64>,
+    pub discord_webhook_url: Option<String>,
+    pub notify_start: bool,
+    pub notify_trail_change: bool,
+    pub notify_end: bool,
+    pub map_theme: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct RiderStatus {
+    pub rider_id: i64,
+    pub is_on_trail: bool,
+    pub beacon_url: Option<String>,
+    pub trail_status_updated: Option<SystemTime>,
+    pub current_trail_id: Option<u64>,
+    pub session_confirmed: bool,
+    pub last_notification: Option<String>,
+    pub last_notification_at: Option<SystemTime>,
+    // the trail the last notification was about, only set for trail changes
+    pub last_notification_trail_id: Option<u64>,
+}
+
+impl RiderStatus {
+    fn empty(rider_id: i64) -> Self {
+        RiderStatus {
+            rider_id,
+            is_on_trail: false,
+            beacon_url: None,
+            trail_status_updated: None,
+            current_trail_id: None,
+            session_confirmed: false,
+            last_notification: None,
+            last_notification_at: None,
+            last_notification_trail_id: None,
+        }
+    }
+}
+
+#[derive(Debug, serde::Deserialize, Clone)]
+struct RiderRow {
+    id: i64,
+    name: String,
+    strava_athlete_id: Option<i64>,
+    discord_webhook_url: Option<String>,
+    notify_start: Option<u8>,
+    notify_trail_change: Option<u8>,
+    notify_end: Option<u8>,
+    map_theme: Option<String>,
+}
+
+impl From<RiderRow> for Rider {
+    fn from(row: RiderRow) -> Self {
+        Rider {
+            id: row.id,
+            name: row.name,
+            strava_athlete_id: row.strava_athlete_id,
+            discord_webhook_url: row.discord_webhook_url,
+            notify_start: row.notify_start != Some(0),
+            notify_trail_change: row.notify_trail_change != Some(0),
+            notify_end: row.notify_end != Some(0),
+            map_theme: row.map_theme,
+        }
+    }
+}
+
+#[derive(Debug, serde::Deserialize, Clone)]
+struct RiderStatusRow {
+    rider_id: i64,
+    is_on_trail: Option<u8>,
+    beacon_url: Option<String>,
+    trail_status_updated: Option<u64>,
+    current_trail_id: Option<u64>,
+    session_confirmed: Option<u8>,
+    last_notification: Option<String>,
+    last_notification_at: Option<u64>,
+    last_notification_trail_id: Option<u64>,
+}
+
+impl From<RiderStatusRow> for RiderStatus {
+    fn from(row: RiderStatusRow) -> Self {
+        RiderStatus {
+            rider_id: row.rider_id,
+            is_on_trail: row.is_on_trail == Some(1),
+            beacon_url: row.beacon_url,
+            trail_status_updated: row
+                .trail_status_updated
+                .map(|secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs)),
+            current_trail_id: row.current_trail_id,
+            session_confirmed: row.session_confirmed == Some(1),
+            last_notification: row.last_notification,
+            last_notification_at: row
+                .last_notification_at
+                .map(|secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs)),
+            last_notification_trail_id: row.last_notification_trail_id,
+        }
+    }
+}
+
+const RIDER_COLUMNS: &str =
+    "id, name, strava_athlete_id, discord_webhook_url, notify_start, notify_trail_change, notify_end, map_theme";
+
+pub async fn get_riders() -> Vec<Rider> {
+    let result = DB_SERVICE
+        .get()
+        .unwrap()
+        .query_many::<RiderRow>(
+            &format!("SELECT {RIDER_COLUMNS} FROM riders ORDER BY id"),
+            libsql::params!(),
+        )
+        .await;
+
+    match result {
+        Ok(rows) => rows.into_iter().map(Rider::from).collect(),
+        Err(e) => {
+            tracing::error!("Failed to get riders from the DB: {:?}", e);
+            Vec::new()
+        }
+    }
+}
+
+// the first registered rider, used when a request doesn't say who it's for
+pub async fn get_primary_rider() -> Option<Rider> {
+    get_riders().await.into_iter().next()
+}
+
+// the rider the stored strava token belongs to (STRAVA_USER_ID), falls back to the primary rider
+// the token can only read its owner's activities, so anything that fetches activities is limited to this rider
+pub async fn get_strava_rider() -> Option<Rider> {
+    let riders = get_riders().await;
+    let strava_user_id = shared_lib::env_utils::get_strava_user_id().and_then(|id| id.parse().ok());
+
+    let owner = riders
+        .iter()
+        .position(|rider| strava_user_id.is_some() && rider.strava_athlete_id == strava_user_id);
+    match owner {
+        Some(index) => riders.into_iter().nth(index),
+        None => riders.into_iter().next(),
+    }
+}
+
+// whether this rider's activities can be fetched with the stored strava token
+pub async fn is_strava_rider(rider_id: i64) -> bool {
+    get_strava_rider()
+        .await
+        .is_some_and(|rider| rider.id == rider_id)
+}
+
+pub async fn get_rider_by_athlete_id(strava_athlete_id: i64) -> Option<Rider> {
+    get_riders()
+        .await
+        .into_iter()
+        .find(|rider| rider.strava_athlete_id == Some(strava_athlete_id))
+}
+
+pub async fn upsert_rider(registration: RiderRegistration) {
+    tracing::debug!("Registering rider in the DB: {}", registration.name);
+    let _ = DB_SERVICE
+        .get()
+        .unwrap()
+        .execute(
+            "INSERT INTO riders (name, strava_athlete_id, discord_webhook_url, notify_start, notify_trail_change, notify_end, map_theme) \
+                VALUES (?, ?, ?, ?, ?, ?, ?) \
+                ON CONFLICT (name) \
+                DO UPDATE SET strava_athlete_id = excluded.strava_athlete_id, discord_webhook_url = excluded.discord_webhook_url, \
+                notify_start = excluded.notify_start, notify_trail_change = excluded.notify_trail_change, notify_end = excluded.notify_end, \
+                map_theme = excluded.map_theme",
+            libsql::params!(
+                registration.name,
+                registration.strava_athlete_id,
+                registration.discord_webhook_url,
+                registration.notify_start as i64,
+                registration.notify_trail_change as i64,
+                registration.notify_end as i64,
+                registration.map_theme
+            ),
+            DBTable::Riders,
+        )
+        .await;
+}
+
+// troy_status predates multiple riders, carry it over as the status of the given rider
+// only fills in a rider_status row that doesn't exist yet
+pub async fn carry_over_legacy_status(rider_id: i64) {
+    let result = DB_SERVICE
+        .get()
+        .unwrap()
+        .try_execute(
+            "INSERT OR IGNORE INTO rider_status (rider_id, is_on_trail, beacon_url, trail_status_updated, current_trail_id, session_confirmed, last_notification, last_notification_at) \
+                SELECT ?, is_on_trail, beacon_url, trail_status_updated, current_trail_id, session_confirmed, last_notification, last_notification_at FROM troy_status",
+            libsql::params!(rider_id),
+            DBTable::RiderStatus,
+        )
+        .await;
+
+    match result {
+        Ok(0) => {}
+        Ok(_) => tracing::info!("Carried over troy_status to rider {}", rider_id),
+        Err(e) => tracing::error!("Failed to carry over troy_status: {:?}", e),
+    }
+}
+
+pub async fn get_rider_status(rider_id: i64) -> RiderStatus {
+    let result = DB_SERVICE
+        .get()
+        .unwrap()
+        .query_many::<RiderStatusRow>(
+            "SELECT * FROM rider_status WHERE rider_id = ?",
+            libsql::params!(rider_id),
+        )
+        .await;
+
+    match result {
+        Ok(rows) => match rows.into_iter().next() {
+            Some(row) => {
+                tracing::trace!("Retrieved rider status from the DB: {:?}", row);
+                row.into()
+            }
+            None => RiderStatus::empty(rider_id),
+        },
+        Err(e) => {
+            tracing::error!("Failed to get rider status from the DB: {:?}", e);
+            RiderStatus::empty(rider_id)
+        }
+    }
+}
+
+// every rider that's currently out, along with their status
+pub async fn get_riders_on_trail() -> Vec<(Rider, RiderStatus)> {
+    let mut riders_on_trail = Vec::new();
+    for rider in get_riders().await {
+        let status = get_rider_status(rider.id).await;
+        if status.is_on_trail {
+            riders_on_trail.push((rider, status));
+        }
+    }
+    riders_on_trail
+}
+
+pub async fn set_on_trail(rider_id: i64, is_on_trail: bool) {
+    let is_on_trail = match is_on_trail {
+        true => 1,
+        false => 0,
+    };
+
+    tracing::debug!(
+        "Updating on trail status for rider {} in the DB to {}",
+        rider_id,
+        is_on_trail
+    );
+
+    let _ = DB_SERVICE.get().unwrap()
+            .execute(
+                "INSERT INTO rider_status (rider_id, is_on_trail, trail_status_updated) \
+                VALUES (?, ?, ?) \
+                ON CONFLICT (rider_id) \
+                DO UPDATE SET is_on_trail = excluded.is_on_trail, trail_status_updated = excluded.trail_status_updated",
+                libsql::params!(rider_id, is_on_trail, unix_timestamp()),
+                DBTable::RiderStatus).await;
+}
+
+pub async fn set_beacon_url(rider_id: i64, beacon_url: Option<String>) {
+    tracing::debug!(
+        "Updating beacon url for rider {} in the DB to {:?}",
+        rider_id,
+        beacon_url
+    );
+    let _ = DB_SERVICE
+        .get()
+        .unwrap()
+        .execute(
+            "INSERT INTO rider_status (rider_id, beacon_url, beacon_url_updated_at) \
+                VALUES (?, ?, ?) \
+                ON CONFLICT (rider_id) \
+                DO UPDATE SET beacon_url = excluded.beacon_url, beacon_url_updated_at = excluded.beacon_url_updated_at",
+            libsql::params!(rider_id, beacon_url, unix_timestamp()),
+            DBTable::RiderStatus,
+        )
+        .await;
+}
+
+// when each rider's beacon url last changed, keyed by rider id
+// syncs with the remote primary first so changes made by other instances show up
+pub async fn get_beacon_url_updates() -> HashMap<i64, i64> {
+    #[derive(Debug, serde::Deserialize, Clone)]
+    struct BeaconUrlUpdateRow {
+        rider_id: i64,
+        beacon_url_updated_at: i64,
+    }
+
+    let db_service = DB_SERVICE.get().unwrap();
+    if let Err(e) = db_service.sync().await {
+        tracing::warn!("Failed to sync with the remote DB: {:?}", e);
+    }
+
+    let result = db_service
+        .query_many::<BeaconUrlUpdateRow>(
+            "SELECT rider_id, beacon_url_updated_at FROM rider_status WHERE beacon_url_updated_at IS NOT NULL",
+            libsql::params!(),
+        )
+        .await;
+
+    match result {
+        Ok(rows) => rows
+            .into_iter()
+            .map(|row| (row.rider_id, row.beacon_url_updated_at))
+            .collect(),
+        Err(e) => {
+            tracing::error!("Failed to get beacon url updates from the DB: {:?}", e);
+            HashMap::new()
+        }
+    }
+}
+
+pub async fn set_current_trail(rider_id: i64, trail_id: Option<u64>) {
+    tracing::debug!(
+        "Updating current trail for rider {} in the DB to {:?}",
+        rider_id,
+        trail_id
+    );
+    let trail_id = trail_id.map(|id| id as i64);
+    let _ = DB_SERVICE
+        .get()
+        .unwrap()
+        .execute(
+            "INSERT INTO rider_status (rider_id, current_trail_id) \
+                VALUES (?, ?) \
+                ON CONFLICT (rider_id) \
+                DO UPDATE SET current_trail_id = excluded.current_trail_id",
+            libsql::params!(rider_id, trail_id),
+            DBTable::RiderStatus,
+        )
+        .await;
+}
+
+pub async fn set_session_confirmed(rider_id: i64, session_confirmed: bool) {
+    let session_confirmed = match session_confirmed {
+        true => 1,
+        false => 0,
+    };
+
+    tracing::debug!(
+        "Updating session confirmed for rider {} in the DB to {}",
+        rider_id,
+        session_confirmed
+    );
+    let _ = DB_SERVICE
+        .get()
+        .unwrap()
+        .execute(
+            "INSERT INTO rider_status (rider_id, session_confirmed) \
+                VALUES (?, ?) \
+                ON CONFLICT (rider_id) \
+                DO UPDATE SET session_confirmed = excluded.session_confirmed",
+            libsql::params!(rider_id, session_confirmed),
+            DBTable::RiderStatus,
+        )
+        .await;
+}
+
+pub async fn set_last_notification(rider_id: i64, notification: &str, trail_id: Option<u64>) {
+    tracing::debug!(
+        "Updating last notification for rider {} in the DB to {} (trail {:?})",
+        rider_id,
+        notification,
+        trail_id
+    );
+    let trail_id = trail_id.map(|id| id as i64);
+    let _ = DB_SERVICE
+        .get()
+        .unwrap()
+        .execute(
+            "INSERT INTO rider_status (rider_id, last_notification, last_notification_at, last_notification_trail_id) \
+                VALUES (?, ?, ?, ?) \
+                ON CONFLICT (rider_id) \
+                DO UPDATE SET last_notification = excluded.last_notification, last_notification_at = excluded.last_notification_at, \
+                last_notification_trail_id = excluded.last_notification_trail_id",
+            libsql::params!(rider_id, notification, unix_timestamp(), trail_id),
+            DBTable::RiderStatus,
+        )
+        .await;
+}
@@ -0,0 +1,118 @@
+use std::collections::HashMap;
+
+use shared_lib::trail_structs::TrailStatus;
+
+use crate::{unix_timestamp, DBTable, DB_SERVICE};
+
+// a row of the trail status history, written whenever a trail system's status or its description changes
+#[derive(Debug, Clone)]
+pub struct TrailStatusChange {
+    pub trail_id: u64,
+    pub status: TrailStatus,
+    pub previous_status: Option<TrailStatus>,
+    pub status_description: Option<String>,
+    // unix timestamp in seconds
+    pub changed_at: i64,
+}
+
+#[derive(Debug, serde::Deserialize, Clone)]
+struct TrailStatusRow {
+    trail_id: i64,
+    status: String,
+    previous_status: Option<String>,
+    status_description: Option<String>,
+    changed_at: i64,
+}
+
+impl From<TrailStatusRow> for TrailStatusChange {
+    fn from(row: TrailStatusRow) -> Self {
+        TrailStatusChange {
+            trail_id: row.trail_id as u64,
+            status: TrailStatus::from(row.status.as_str()),
+            previous_status: row.previous_status.as_deref().map(TrailStatus::from),
+            status_description: row.status_description,
+            changed_at: row.changed_at,
+        }
+    }
+}
+
+// the last change recorded for each trail system
+pub async fn get_latest_statuses() -> HashMap<u64, TrailStatusChange> {
+    // sqlite takes the bare columns from the row that has the MAX
+    let result = DB_SERVICE
+        .get()
+        .unwrap()
+        .query_many::<TrailStatusRow>(
+            "SELECT trail_id, status, previous_status, status_description, MAX(changed_at) AS changed_at FROM trail_status_history GROUP BY trail_id",
+            libsql::params!(),
+        )
+        .await;
+
+    match result {
+        Ok(rows) => rows
+            .into_iter()
+            .map(|row| (row.trail_id as u64, row.into()))
+            .collect(),
+        Err(e) => {
+            tracing::error!("Failed to get trail statuses from the DB: {:?}", e);
+            HashMap::new()
+        }
+    }
+}
+
+// previous_status is None the first time a trail system is seen
+pub async fn record_status_change(
+    trail_id: u64,
+    previous_status: Option<&TrailStatus>,
+    status: &TrailStatus,
+    status_description: &str,
+) -> anyhow::Result<()> {
+    DB_SERVICE
+        .get()
+        .unwrap()
+        .execute(
+            "INSERT INTO trail_status_history (trail_id, status, previous_status, status_description, changed_at) VALUES (?, ?, ?, ?, ?)",
+            libsql::params!(
+                trail_id as i64,
+                status.to_string(),
+                previous_status.map(|status| status.to_string()),
+                status_description,
+                unix_timestamp()
+            ),
+            DBTable::TrailStatusHistory,
+        )
+        .await?;
+
+    Ok(())
+}
+
+// oldest first, every trail system's history when no trail is given
+pub async fn get_history(trail_id: Option<u64>) -> Vec<TrailStatusChange> {
+    let db_service = DB_SERVICE.get().unwrap();
+    let result = match trail_id {
+        Some(trail_id) => {
+            db_service
+                .query_many::<TrailStatusRow>(
+                    "SELECT trail_id, status, previous_status, status_description, changed_at FROM trail_status_history WHERE trail_id = ? ORDER BY changed_at, id",
+                    libsql::params!(trail_id as i64),
+                )
+                .await
+        }
+        None => {
+            db_service
+                .query_many::<TrailStatusRow>(
+                    "SELECT trail_id, status, previous_status, status_description, changed_at FROM trail_status_history ORDER BY changed_at, id",
+                    libsql::params!(),
+                )
+                .await
+        }
+    };
+
+    match result {
+        Ok(rows) => rows.into_iter().map(TrailStatusChange::from).collect(),
+        Err(e) => {
+            tracing::error!("Failed to get trail status history from the DB: {:?}", e);
+            Vec::new()
+        }
+    }
+}
@@ -0,0 +1,25 @@
+use crate::{unix_timestamp, DBTable, DB_SERVICE};
+
+// records a webhook signature as used until expires_at (a unix timestamp in seconds)
+// returns false if the signature was already used, the unique key makes this hold across instances
+pub async fn claim(signature: &str, expires_at: i64) -> anyhow::Result<bool> {
+    let db_service = DB_SERVICE.get().unwrap();
+
+    db_service
+        .try_execute(
+            "DELETE FROM webhook_signatures WHERE expires_at < ?",
+            libsql::params!(unix_timestamp()),
+            DBTable::WebhookSignatures,
+        )
+        .await?;
+
+    let rows_affected = db_service
+        .try_execute(
+            "INSERT OR IGNORE INTO webhook_signatures (signature, expires_at) VALUES (?, ?)",
+            libsql::params!(signature, expires_at),
+            DBTable::WebhookSignatures,
+        )
+        .await?;
+
+    Ok(rows_affected > 0)
+}
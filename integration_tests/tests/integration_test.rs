@@ -22,3 +22,20 @@ async fn external_test() {
 
     tracing::info!("Fetched rides, fetching trails");
 }
+
+#[test]
+fn route_svg_test() {
+    let options = map_service::route_svg::SvgOptions {
+        show_start_end: true,
+        title: Some("Lunch <ride> & more".to_string()),
+        ..Default::default()
+    };
+
+    let svg = map_service::route_svg::render_route_svg("_p~iF~ps|U_ulLnnqC_mqNvxq`@", &options)
+        .expect("Failed to render route svg");
+
+    assert!(svg.contains("<polyline"));
+    assert_eq!(svg.matches("<circle").count(), 2);
+    assert!(svg.contains("Lunch &lt;ride&gt; &amp; more"));
+    map_service::svg::render_svg(svg.as_bytes(), 300.0).expect("Route svg should parse");
+}
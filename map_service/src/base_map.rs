@@ -0,0 +1,200 @@
+use image::{load_from_memory, Rgba, RgbaImage};
+use staticmap::tools::Tool;
+use staticmap::{lat_to_y, lon_to_x, Bounds};
+use tiny_skia::{IntSize, Pixmap};
+
+use crate::tiles::TileProvider;
+
+const TILE_SIZE: u32 = 256;
+const MAX_ZOOM: u8 = 17;
+
+// tools are drawn after the tiles are fetched, so they need to be able to cross an await
+pub type MapTool = Box<dyn Tool + Send + Sync>;
+
+// shown wherever a tile couldn't be loaded
+const BACKGROUND_COLOR: Rgba<u8> = Rgba([58, 64, 72, 255]);
+
+// renders the tools over the provider's tiles, sized and zoomed to fit the tools like staticmap does
+// tiles that fail to load are left as the plain background color instead of failing the whole map
+pub async fn render(
+    provider: Option<&TileProvider>,
+    tools: &[MapTool],
+    width: u32,
+    height: u32,
+    padding: (u32, u32),
+) -> anyhow::Result<RgbaImage> {
+    let (base_img, bounds) = render_tiles(provider, tools, width, height, padding).await;
+    draw_tools(base_img, tools, &bounds)
+}
+
+// just the tiles, the tools are only used to work out the bounds so they can be drawn later
+pub async fn render_tiles(
+    provider: Option<&TileProvider>,
+    tools: &[MapTool],
+    width: u32,
+    height: u32,
+    padding: (u32, u32),
+) -> (RgbaImage, Bounds) {
+    let bounds = calculate_bounds(tools, width, height, padding);
+
+    let mut base_img = RgbaImage::from_pixel(width, height, BACKGROUND_COLOR);
+    if let Some(provider) = provider {
+        draw_tiles(provider, &bounds, &mut base_img).await;
+    }
+
+    (base_img, bounds)
+}
+
+// draws the tools over tiles from render_tiles, this doesn't await so it can run on a blocking thread
+pub fn draw_tools(
+    base_img: RgbaImage,
+    tools: &[MapTool],
+    bounds: &Bounds,
+) -> anyhow::Result<RgbaImage> {
+    let mut pixmap = rgba_to_pixmap(base_img)?;
+    for tool in tools {
+        tool.draw(bounds, pixmap.as_mut());
+    }
+
+    pixmap_to_rgba(pixmap)
+}
+
+async fn draw_tiles(provider: &TileProvider, bounds: &Bounds, base_img: &mut RgbaImage) {
+    let max_tile: i32 = 2_i32.pow(bounds.zoom.into());
+
+    let mut tile_requests = tokio::task::JoinSet::new();
+    for x in bounds.x_min..bounds.x_max {
+        for y in bounds.y_min..bounds.y_max {
+            let provider = provider.clone();
+            let zoom = bounds.zoom;
+            let tile_x = ((x + max_tile) % max_tile) as u32;
+            let tile_y = ((y + max_tile) % max_tile) as u32;
+            tile_requests.spawn(async move {
+                let tile = provider.get_tile(zoom, tile_x, tile_y).await;
+                (x, y, tile)
+            });
+        }
+    }
+
+    let mut failed_tiles = 0;
+    while let Some(result) = tile_requests.join_next().await {
+        let (x, y, tile) = match result {
+            Ok(result) => result,
+            Err(e) => {
+                tracing::error!("Tile request task failed: {:?}", e);
+                failed_tiles += 1;
+                continue;
+            }
+        };
+
+        let tile_img = match tile.and_then(|data| Ok(load_from_memory(&data)?)) {
+            Ok(tile_img) => tile_img.to_rgba8(),
+            Err(e) => {
+                tracing::debug!("Failed to load tile {}/{}/{}: {:?}", bounds.zoom, x, y, e);
+                failed_tiles += 1;
+                continue;
+            }
+        };
+
+        let x_px = bounds.x_to_px(x.into()) as i64;
+        let y_px = bounds.y_to_px(y.into()) as i64;
+        image::imageops::overlay(base_img, &tile_img, x_px, y_px);
+    }
+
+    if let Some(cache) = crate::tile_cache::get_tile_cache() {
+        cache.prune_if_needed().await;
+    }
+
+    if failed_tiles > 0 {
+        tracing::warn!(
+            "{} map tiles from {} couldn't be loaded, using a plain background for them",
+            failed_tiles,
+            provider.name()
+        );
+    }
+}
+
+// mirrors staticmap's bounds calculation: the highest zoom where every tool fits inside the padding
+pub fn calculate_bounds(tools: &[MapTool], width: u32, height: u32, padding: (u32, u32)) -> Bounds {
+    let extent_at = |zoom: u8| {
+        let extents: Vec<(f64, f64, f64, f64)> = tools
+            .iter()
+            .map(|tool| tool.extent(zoom, TILE_SIZE.into()))
+            .collect();
+        (
+            extents.iter().map(|e| e.0).fold(f64::NAN, f64::min),
+            extents.iter().map(|e| e.1).fold(f64::NAN, f64::min),
+            extents.iter().map(|e| e.2).fold(f64::NAN, f64::max),
+            extents.iter().map(|e| e.3).fold(f64::NAN, f64::max),
+        )
+    };
+
+    let mut zoom = 1;
+    for z in (0..=MAX_ZOOM).rev() {
+        let (lon_min, lat_min, lon_max, lat_max) = extent_at(z);
+        let extent_width = (lon_to_x(lon_max, z) - lon_to_x(lon_min, z)) * f64::from(TILE_SIZE);
+        let extent_height = (lat_to_y(lat_min, z) - lat_to_y(lat_max, z)) * f64::from(TILE_SIZE);
+
+        if extent_width > f64::from(width - padding.0 * 2)
+            || extent_height > f64::from(height - padding.1 * 2)
+        {
+            continue;
+        }
+
+        zoom = z;
+        break;
+    }
+
+    let (lon_min, lat_min, lon_max, lat_max) = extent_at(zoom);
+    let x_center = lon_to_x((lon_min + lon_max) / 2., zoom);
+    let y_center = lat_to_y((lat_min + lat_max) / 2., zoom);
+
+    let x_m = 0.5 * f64::from(width) / f64::from(TILE_SIZE);
+    let y_m = 0.5 * f64::from(height) / f64::from(TILE_SIZE);
+
+    Bounds {
+        height,
+        width,
+        x_center,
+        y_center,
+        x_min: (x_center - x_m).floor() as i32,
+        x_max: (x_center + x_m).ceil() as i32,
+        y_min: (y_center - y_m).floor() as i32,
+        y_max: (y_center + y_m).ceil() as i32,
+        tile_size: TILE_SIZE,
+        zoom,
+    }
+}
+
+// tiny-skia works with premultiplied alpha, image with straight alpha
+pub fn rgba_to_pixmap(img: RgbaImage) -> anyhow::Result<Pixmap> {
+    let size = IntSize::from_wh(img.width(), img.height())
+        .ok_or_else(|| anyhow::anyhow!("Invalid image dimensions"))?;
+
+    let mut data = img.into_raw();
+    for pixel in data.chunks_exact_mut(4) {
+        let a = pixel[3] as f32 / 255.0;
+        pixel[0] = (pixel[0] as f32 * a).round() as u8;
+        pixel[1] = (pixel[1] as f32 * a).round() as u8;
+        pixel[2] = (pixel[2] as f32 * a).round() as u8;
+    }
+
+    Pixmap::from_vec(data, size).ok_or_else(|| anyhow::anyhow!("Failed to create pixmap"))
+}
+
+pub fn pixmap_to_rgba(pixmap: Pixmap) -> anyhow::Result<RgbaImage> {
+    let (width, height) = (pixmap.width(), pixmap.height());
+
+    let mut data = pixmap.take();
+    for pixel in data.chunks_exact_mut(4) {
+        let a = pixel[3] as f32 / 255.0;
+        if a > 0.0 {
+            pixel[0] = (pixel[0] as f32 / a).min(255.0) as u8;
+            pixel[1] = (pixel[1] as f32 / a).min(255.0) as u8;
+            pixel[2] = (pixel[2] as f32 / a).min(255.0) as u8;
+        }
+    }
+
+    RgbaImage::from_raw(width, height, data)
+        .ok_or_else(|| anyhow::anyhow!("Failed to create image buffer"))
+}
@@ -0,0 +1,309 @@
+use std::io::Cursor;
+
+use ab_glyph::{FontArc, PxScale};
+use image::{DynamicImage, ImageFormat, Rgba, RgbaImage};
+use imageproc::drawing::{draw_text_mut, text_size};
+use shared_lib::strava_structs::ActivityStreams;
+use shared_lib::utils::{format_thousands, meters_to_feet, meters_to_miles, mps_to_miph};
+use tiny_skia::{Color, FillRule, Paint, PathBuilder, Pixmap, Stroke, Transform};
+
+use crate::base_map::pixmap_to_rgba;
+use crate::theme::Theme;
+
+const LABEL_SIZE: f32 = 22.0;
+const AXIS_MARGIN_LEFT: u32 = 110;
+const AXIS_MARGIN_RIGHT: u32 = 90;
+const AXIS_MARGIN_BOTTOM: u32 = 30;
+
+const SPEED_COLOR: (u8, u8, u8) = (120, 190, 255);
+const BACKGROUND_COLOR: Rgba<u8> = Rgba([24, 26, 30, 255]);
+
+// elevation (and optionally speed) over distance, everything in meters and meters per second
+#[derive(Debug, Clone)]
+pub struct ProfileData {
+    pub distance: Vec<f64>,
+    pub elevation: Vec<f64>,
+    pub speed: Option<Vec<f64>>,
+}
+
+impl ProfileData {
+    // None when the activity has no altitude data, e.g. indoor rides
+    pub fn from_streams(streams: &ActivityStreams, include_speed: bool) -> Option<Self> {
+        let distance = &streams.distance.data;
+        let elevation = &streams.altitude.data;
+        if distance.len() < 2 || distance.len() != elevation.len() {
+            return None;
+        }
+
+        let speed = &streams.velocity_smooth.data;
+        let speed = (include_speed && speed.len() == distance.len()).then(|| speed.clone());
+
+        Some(ProfileData {
+            distance: distance.clone(),
+            elevation: elevation.clone(),
+            speed,
+        })
+    }
+
+    // for when there's only a polyline plus an altitude for each of its points
+    pub fn from_polyline(polyline: &str, altitude: &[f64]) -> anyhow::Result<Self> {
+        let line_string = polyline::decode_polyline(polyline, 5)?;
+        if line_string.0.len() != altitude.len() {
+            return Err(anyhow::anyhow!(
+                "Polyline has {} points but there are {} altitudes",
+                line_string.0.len(),
+                altitude.len()
+            ));
+        }
+
+        let mut distance = Vec::with_capacity(altitude.len());
+        let mut total = 0.0;
+        for (i, coord) in line_string.coords().enumerate() {
+            if i > 0 {
+                let previous = line_string.0[i - 1];
+                total += shared_lib::utils::haversine_distance(
+                    geo_types::Point::from(previous),
+                    geo_types::Point::from(*coord),
+                )?;
+            }
+            distance.push(total);
+        }
+
+        Ok(ProfileData {
+            distance,
+            elevation: altitude.to_vec(),
+            speed: None,
+        })
+    }
+}
+
+// the part of an image the chart is drawn into
+#[derive(Debug, Copy, Clone)]
+pub struct ChartArea {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+// renders the profile on its own, for the ride pages
+pub fn render_profile_png(
+    data: &ProfileData,
+    width: u32,
+    height: u32,
+    theme: &Theme,
+) -> anyhow::Result<Vec<u8>> {
+    let font = theme.font()?;
+
+    let mut img = RgbaImage::from_pixel(width, height, BACKGROUND_COLOR);
+    draw_profile(
+        &mut img,
+        &font,
+        data,
+        ChartArea {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        },
+        theme,
+    )?;
+
+    let mut output_bytes = Vec::new();
+    DynamicImage::ImageRgba8(img)
+        .write_to(&mut Cursor::new(&mut output_bytes), ImageFormat::Png)?;
+    Ok(output_bytes)
+}
+
+pub fn draw_profile(
+    img: &mut RgbaImage,
+    font: &FontArc,
+    data: &ProfileData,
+    area: ChartArea,
+    theme: &Theme,
+) -> anyhow::Result<()> {
+    // elevation in the route color, labels in the text color
+    let [r, g, b, _] = theme.route_color.0;
+    let elevation_color = (r, g, b);
+    let label_color: Rgba<u8> = theme.text_color.into();
+
+    let right_margin = match data.speed {
+        Some(_) => AXIS_MARGIN_RIGHT,
+        None => AXIS_MARGIN_RIGHT / 4,
+    };
+    let plot_width = area.width.saturating_sub(AXIS_MARGIN_LEFT + right_margin);
+    let plot_height = area
+        .height
+        .saturating_sub(AXIS_MARGIN_BOTTOM + LABEL_SIZE as u32 / 2);
+    if plot_width == 0 || plot_height == 0 || data.distance.len() < 2 {
+        return Err(anyhow::anyhow!("Not enough room or data for a profile"));
+    }
+
+    let max_distance = data.distance.last().copied().unwrap_or_default().max(1.0);
+    let (min_elevation, max_elevation) = min_max(&data.elevation);
+    // keep flat rides from turning into a wall
+    let elevation_range = (max_elevation - min_elevation).max(30.0);
+
+    let mut pixmap = Pixmap::new(plot_width, plot_height)
+        .ok_or_else(|| anyhow::anyhow!("Invalid chart dimensions"))?;
+    let (w, h) = (plot_width as f32, plot_height as f32);
+    let to_x = |distance: f64| (distance / max_distance) as f32 * w;
+
+    let elevation_points: Vec<(f32, f32)> = data
+        .distance
+        .iter()
+        .zip(&data.elevation)
+        .map(|(distance, elevation)| {
+            let y = h - ((elevation - min_elevation) / elevation_range) as f32 * (h - 2.0);
+            (to_x(*distance), y)
+        })
+        .collect();
+
+    // filled area under the elevation line
+    {
+        let mut pb = PathBuilder::new();
+        pb.move_to(0.0, h);
+        for (x, y) in &elevation_points {
+            pb.line_to(*x, *y);
+        }
+        pb.line_to(w, h);
+        pb.close();
+
+        if let Some(path) = pb.finish() {
+            let mut paint = Paint::default();
+            let (r, g, b) = elevation_color;
+            paint.set_color(Color::from_rgba8(r, g, b, 90));
+            paint.anti_alias = true;
+            pixmap.fill_path(
+                &path,
+                &paint,
+                FillRule::Winding,
+                Transform::identity(),
+                None,
+            );
+        }
+    }
+
+    stroke_line(&mut pixmap, &elevation_points, elevation_color, 2.5);
+
+    let speed_range = data.speed.as_ref().map(|speed| {
+        let (_, max_speed) = min_max(speed);
+        let max_speed = max_speed.max(1.0);
+        let speed_points: Vec<(f32, f32)> = data
+            .distance
+            .iter()
+            .zip(speed)
+            .map(|(distance, speed)| (to_x(*distance), h - (speed / max_speed) as f32 * (h - 2.0)))
+            .collect();
+        stroke_line(&mut pixmap, &speed_points, SPEED_COLOR, 1.5);
+        max_speed
+    });
+
+    let chart_img = pixmap_to_rgba(pixmap)?;
+    let plot_x = area.x + AXIS_MARGIN_LEFT;
+    let plot_y = area.y + LABEL_SIZE as u32 / 2;
+    image::imageops::overlay(img, &chart_img, plot_x.into(), plot_y.into());
+
+    // axis labels, feet on the left, miles along the bottom, mph on the right
+    let scale = PxScale::from(LABEL_SIZE);
+    let label_x = |text: &str| {
+        let (text_width, _) = text_size(scale, font, text);
+        (area.x + AXIS_MARGIN_LEFT).saturating_sub(text_width + 10) as i32
+    };
+
+    let top_label = format!(
+        "{} ft",
+        format_thousands(meters_to_feet(min_elevation + elevation_range, true))
+    );
+    let bottom_label = format!(
+        "{} ft",
+        format_thousands(meters_to_feet(min_elevation, true))
+    );
+    draw_text_mut(
+        img,
+        label_color,
+        label_x(&top_label),
+        plot_y as i32 - 8,
+        scale,
+        font,
+        &top_label,
+    );
+    draw_text_mut(
+        img,
+        label_color,
+        label_x(&bottom_label),
+        (plot_y + plot_height) as i32 - LABEL_SIZE as i32,
+        scale,
+        font,
+        &bottom_label,
+    );
+
+    let distance_y = (plot_y + plot_height + 4) as i32;
+    let end_label = format!("{} mi", meters_to_miles(max_distance, false));
+    let (end_width, _) = text_size(scale, font, &end_label);
+    draw_text_mut(
+        img,
+        label_color,
+        plot_x as i32,
+        distance_y,
+        scale,
+        font,
+        "0 mi",
+    );
+    draw_text_mut(
+        img,
+        label_color,
+        (plot_x + plot_width) as i32 - end_width as i32,
+        distance_y,
+        scale,
+        font,
+        &end_label,
+    );
+
+    if let Some(max_speed) = speed_range {
+        let speed_label = format!("{} mph", mps_to_miph(max_speed, true));
+        let (r, g, b) = SPEED_COLOR;
+        draw_text_mut(
+            img,
+            Rgba([r, g, b, 255]),
+            (plot_x + plot_width + 10) as i32,
+            plot_y as i32 - 8,
+            scale,
+            font,
+            &speed_label,
+        );
+    }
+
+    Ok(())
+}
+
+fn stroke_line(pixmap: &mut Pixmap, points: &[(f32, f32)], color: (u8, u8, u8), width: f32) {
+    let mut pb = PathBuilder::new();
+    for (i, (x, y)) in points.iter().enumerate() {
+        if i == 0 {
+            pb.move_to(*x, *y);
+        } else {
+            pb.line_to(*x, *y);
+        }
+    }
+
+    if let Some(path) = pb.finish() {
+        let mut paint = Paint::default();
+        let (r, g, b) = color;
+        paint.set_color(Color::from_rgba8(r, g, b, 255));
+        paint.anti_alias = true;
+        let stroke = Stroke {
+            width,
+            ..Stroke::default()
+        };
+        pixmap.stroke_path(&path, &paint, &stroke, Transform::identity(), None);
+    }
+}
+
+fn min_max(values: &[f64]) -> (f64, f64) {
+    values
+        .iter()
+        .fold((f64::MAX, f64::MIN), |(min, max), value| {
+            (min.min(*value), max.max(*value))
+        })
+}
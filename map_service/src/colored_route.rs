@@ -0,0 +1,254 @@
+use ab_glyph::{FontArc, PxScale};
+use image::{Rgba, RgbaImage};
+use imageproc::drawing::{draw_filled_rect_mut, draw_text_mut, text_size};
+use imageproc::rect::Rect;
+use shared_lib::strava_structs::ActivityStreams;
+use shared_lib::utils::mps_to_miph;
+use staticmap::tools::Tool;
+use staticmap::{lat_to_y, lon_to_x, Bounds};
+use tiny_skia::{LineCap, Paint, PathBuilder, PixmapMut, Stroke, Transform};
+
+// low to high, blue through green and yellow to red
+const COLOR_SCALE: [(u8, u8, u8); 5] = [
+    (49, 130, 255),
+    (38, 200, 120),
+    (250, 220, 50),
+    (255, 140, 0),
+    (230, 40, 40),
+];
+
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub enum RouteStyle {
+    // a single orange line
+    #[default]
+    Solid,
+    Speed,
+    Gradient,
+    HeartRate,
+}
+
+impl RouteStyle {
+    fn legend_title(&self) -> &'static str {
+        match self {
+            RouteStyle::Solid => "",
+            RouteStyle::Speed => "Speed",
+            RouteStyle::Gradient => "Gradient",
+            RouteStyle::HeartRate => "Heart rate",
+        }
+    }
+
+    fn format_value(&self, value: f64) -> String {
+        match self {
+            RouteStyle::Solid => String::new(),
+            RouteStyle::Speed => format!("{} mph", mps_to_miph(value, true)),
+            RouteStyle::Gradient => format!("{}%", value.round()),
+            RouteStyle::HeartRate => format!("{} bpm", value.round()),
+        }
+    }
+}
+
+impl From<&str> for RouteStyle {
+    fn from(value: &str) -> Self {
+        match value.to_lowercase().as_str() {
+            "speed" => RouteStyle::Speed,
+            "gradient" | "grade" => RouteStyle::Gradient,
+            "heartrate" | "heart_rate" | "hr" => RouteStyle::HeartRate,
+            _ => RouteStyle::Solid,
+        }
+    }
+}
+
+// the track drawn as short segments, each colored by the stream value at its start
+pub struct ColoredRoute {
+    lat_coordinates: Vec<f64>,
+    lon_coordinates: Vec<f64>,
+    values: Vec<f64>,
+    range: (f64, f64),
+    width: f32,
+}
+
+impl ColoredRoute {
+    // None when the streams don't have what the style needs, the caller falls back to a solid line
+    pub fn from_streams(streams: &ActivityStreams, style: RouteStyle, width: f32) -> Option<Self> {
+        let values = match style {
+            RouteStyle::Solid => return None,
+            RouteStyle::Speed => &streams.velocity_smooth.data,
+            RouteStyle::Gradient => &streams.grade_smooth.data,
+            RouteStyle::HeartRate => &streams.heartrate.data,
+        };
+
+        let latlng = &streams.latlng.data;
+        if latlng.len() < 2 || latlng.len() != values.len() {
+            return None;
+        }
+
+        let (lat_coordinates, lon_coordinates): (Vec<f64>, Vec<f64>) = latlng
+            .iter()
+            .filter(|latlng| latlng.len() == 2)
+            .map(|latlng| (latlng[0], latlng[1]))
+            .unzip();
+        if lat_coordinates.len() != values.len() {
+            return None;
+        }
+
+        let range = match style {
+            // keep the scale symmetric so flat ground is always the middle of the scale
+            RouteStyle::Gradient => (-15.0, 15.0),
+            // ignore the odd spike from a gps glitch or a bad hr strap
+            _ => (percentile(values, 0.05), percentile(values, 0.95)),
+        };
+
+        Some(ColoredRoute {
+            lat_coordinates,
+            lon_coordinates,
+            values: values.clone(),
+            range,
+            width,
+        })
+    }
+
+    pub fn range(&self) -> (f64, f64) {
+        self.range
+    }
+}
+
+impl Tool for ColoredRoute {
+    fn extent(&self, _: u8, _: f64) -> (f64, f64, f64, f64) {
+        (
+            self.lon_coordinates
+                .iter()
+                .copied()
+                .fold(f64::NAN, f64::min),
+            self.lat_coordinates
+                .iter()
+                .copied()
+                .fold(f64::NAN, f64::min),
+            self.lon_coordinates
+                .iter()
+                .copied()
+                .fold(f64::NAN, f64::max),
+            self.lat_coordinates
+                .iter()
+                .copied()
+                .fold(f64::NAN, f64::max),
+        )
+    }
+
+    fn draw(&self, bounds: &Bounds, mut pixmap: PixmapMut) {
+        let points: Vec<(f32, f32)> = self
+            .lon_coordinates
+            .iter()
+            .zip(&self.lat_coordinates)
+            .map(|(lon, lat)| {
+                (
+                    bounds.x_to_px(lon_to_x(*lon, bounds.zoom)) as f32,
+                    bounds.y_to_px(lat_to_y(*lat, bounds.zoom)) as f32,
+                )
+            })
+            .collect();
+
+        let stroke = Stroke {
+            width: self.width,
+            line_cap: LineCap::Round,
+            ..Stroke::default()
+        };
+
+        for (segment, value) in points.windows(2).zip(&self.values) {
+            let mut pb = PathBuilder::new();
+            pb.move_to(segment[0].0, segment[0].1);
+            pb.line_to(segment[1].0, segment[1].1);
+
+            if let Some(path) = pb.finish() {
+                let (r, g, b) = color_for(*value, self.range);
+                let mut paint = Paint::default();
+                paint.set_color_rgba8(r, g, b, 255);
+                paint.anti_alias = true;
+                pixmap.stroke_path(&path, &paint, &stroke, Transform::default(), None);
+            }
+        }
+    }
+}
+
+// what the colors on a colored route mean, drawn in the top left corner
+#[derive(Debug, Clone)]
+pub struct Legend {
+    pub style: RouteStyle,
+    pub range: (f64, f64),
+}
+
+impl Legend {
+    pub fn draw(&self, img: &mut RgbaImage, font: &FontArc, text_color: Rgba<u8>) {
+        const X: i32 = 24;
+        const Y: i32 = 20;
+        const BAR_WIDTH: u32 = 180;
+        const BAR_HEIGHT: u32 = 12;
+        const LABEL_SIZE: f32 = 22.0;
+
+        let scale = PxScale::from(LABEL_SIZE);
+
+        draw_text_mut(
+            img,
+            text_color,
+            X,
+            Y,
+            scale,
+            font,
+            self.style.legend_title(),
+        );
+
+        let bar_y = Y + LABEL_SIZE as i32 + 6;
+        for i in 0..BAR_WIDTH {
+            let t = i as f64 / (BAR_WIDTH - 1) as f64;
+            let (r, g, b) = color_for(t, (0.0, 1.0));
+            draw_filled_rect_mut(
+                img,
+                Rect::at(X + i as i32, bar_y).of_size(1, BAR_HEIGHT),
+                Rgba([r, g, b, 255]),
+            );
+        }
+
+        let labels_y = bar_y + BAR_HEIGHT as i32 + 4;
+        let min_label = self.style.format_value(self.range.0);
+        let max_label = self.style.format_value(self.range.1);
+        let (max_width, _) = text_size(scale, font, &max_label);
+        draw_text_mut(img, text_color, X, labels_y, scale, font, &min_label);
+        draw_text_mut(
+            img,
+            text_color,
+            X + BAR_WIDTH as i32 - max_width as i32,
+            labels_y,
+            scale,
+            font,
+            &max_label,
+        );
+    }
+}
+
+fn color_for(value: f64, (min, max): (f64, f64)) -> (u8, u8, u8) {
+    let t = match max > min {
+        true => ((value - min) / (max - min)).clamp(0.0, 1.0),
+        false => 0.5,
+    };
+
+    interpolate_color(&COLOR_SCALE, t)
+}
+
+// the color t of the way along the scale, t is from 0 to 1
+pub(crate) fn interpolate_color(scale: &[(u8, u8, u8)], t: f64) -> (u8, u8, u8) {
+    let position = t.clamp(0.0, 1.0) * (scale.len() - 1) as f64;
+    let index = (position.floor() as usize).min(scale.len() - 2);
+    let fraction = position - index as f64;
+
+    let (r1, g1, b1) = scale[index];
+    let (r2, g2, b2) = scale[index + 1];
+    let mix = |a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * fraction).round() as u8;
+
+    (mix(r1, r2), mix(g1, g2), mix(b1, b2))
+}
+
+fn percentile(values: &[f64], p: f64) -> f64 {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let index = ((sorted.len() - 1) as f64 * p).round() as usize;
+    sorted[index]
+}
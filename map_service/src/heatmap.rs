@@ -0,0 +1,256 @@
+use geo_types::{Coord, LineString};
+use image::{DynamicImage, Rgba, RgbaImage};
+use staticmap::tools::Tool;
+use staticmap::{lat_to_y, lon_to_x, Bounds};
+use tiny_skia::{Paint, PathBuilder, PixmapMut, PixmapPaint, Stroke, StrokeDash, Transform};
+
+use crate::base_map::{self, MapTool};
+use crate::colored_route::interpolate_color;
+use crate::output::{self, ImageSize, OutputFormat};
+use crate::theme::Theme;
+use crate::tiles::TileProvider;
+use crate::Overlay;
+
+// from rarely ridden to ridden all the time
+const HEAT_SCALE: [(u8, u8, u8); 5] = [
+    (120, 10, 30),
+    (230, 40, 40),
+    (255, 140, 0),
+    (250, 220, 50),
+    (255, 255, 230),
+];
+// how many pixels wide each track is drawn
+const TRACK_WIDTH: i32 = 2;
+const METERS_PER_DEGREE: f64 = 111_320.0;
+
+#[derive(Debug, Copy, Clone)]
+pub struct BoundingBox {
+    pub min_lng: f64,
+    pub min_lat: f64,
+    pub max_lng: f64,
+    pub max_lat: f64,
+}
+
+impl BoundingBox {
+    // a square reaching radius meters out from the point on each side
+    pub fn around(lat: f64, lng: f64, radius: f64) -> Self {
+        let lat_delta = radius / METERS_PER_DEGREE;
+        let lng_delta = radius / (METERS_PER_DEGREE * lat.to_radians().cos().max(0.01));
+
+        BoundingBox {
+            min_lng: lng - lng_delta,
+            min_lat: lat - lat_delta,
+            max_lng: lng + lng_delta,
+            max_lat: lat + lat_delta,
+        }
+    }
+
+    pub fn contains(&self, coord: &Coord) -> bool {
+        (self.min_lng..=self.max_lng).contains(&coord.x)
+            && (self.min_lat..=self.max_lat).contains(&coord.y)
+    }
+}
+
+// every ride drawn on top of each other, the more rides that went through a spot the brighter it is
+pub struct Heatmap {
+    tracks: Vec<LineString>,
+    clip: Option<BoundingBox>,
+    // rings of (lng, lat) drawn as a dashed line over the heat, like a trail system's geofence
+    outline: Vec<Vec<(f64, f64)>>,
+}
+
+impl Heatmap {
+    // with a clip only rides that go through the box are drawn, and the map shows just the box
+    pub fn from_polylines<'a>(
+        polylines: impl IntoIterator<Item = &'a str>,
+        clip: Option<BoundingBox>,
+    ) -> Self {
+        let tracks = polylines
+            .into_iter()
+            .filter_map(|polyline| match polyline::decode_polyline(polyline, 5) {
+                Ok(line_string) => Some(line_string),
+                Err(e) => {
+                    tracing::debug!("Skipping a ride that couldn't be decoded: {}", e);
+                    None
+                }
+            })
+            .filter(|line_string| line_string.0.len() >= 2)
+            .filter(|line_string| match &clip {
+                Some(clip) => line_string.coords().any(|coord| clip.contains(coord)),
+                None => true,
+            })
+            .collect();
+
+        Heatmap {
+            tracks,
+            clip,
+            outline: Vec::new(),
+        }
+    }
+
+    pub fn with_outline(mut self, outline: Vec<Vec<(f64, f64)>>) -> Self {
+        self.outline = outline;
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.tracks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tracks.is_empty()
+    }
+}
+
+impl Tool for Heatmap {
+    fn extent(&self, _: u8, _: f64) -> (f64, f64, f64, f64) {
+        if let Some(clip) = &self.clip {
+            return (clip.min_lng, clip.min_lat, clip.max_lng, clip.max_lat);
+        }
+
+        let coords = || self.tracks.iter().flat_map(|track| track.coords());
+        (
+            coords().map(|coord| coord.x).fold(f64::NAN, f64::min),
+            coords().map(|coord| coord.y).fold(f64::NAN, f64::min),
+            coords().map(|coord| coord.x).fold(f64::NAN, f64::max),
+            coords().map(|coord| coord.y).fold(f64::NAN, f64::max),
+        )
+    }
+
+    fn draw(&self, bounds: &Bounds, mut pixmap: PixmapMut) {
+        let (width, height) = (pixmap.width() as i32, pixmap.height() as i32);
+
+        // how many rides went through each pixel, a ride only counts once per pixel
+        let mut counts = vec![0u32; (width * height) as usize];
+        let mut last_track = vec![usize::MAX; (width * height) as usize];
+
+        for (track_index, track) in self.tracks.iter().enumerate() {
+            let points: Vec<(f64, f64)> = track
+                .coords()
+                .map(|coord| {
+                    (
+                        bounds.x_to_px(lon_to_x(coord.x, bounds.zoom)),
+                        bounds.y_to_px(lat_to_y(coord.y, bounds.zoom)),
+                    )
+                })
+                .collect();
+
+            for segment in points.windows(2) {
+                let ((x1, y1), (x2, y2)) = (segment[0], segment[1]);
+                let steps = (x2 - x1).abs().max((y2 - y1).abs()).ceil().max(1.0) as i32;
+
+                for step in 0..=steps {
+                    let t = step as f64 / steps as f64;
+                    let x = (x1 + (x2 - x1) * t) as i32;
+                    let y = (y1 + (y2 - y1) * t) as i32;
+
+                    for (px, py) in (0..TRACK_WIDTH)
+                        .flat_map(|dx| (0..TRACK_WIDTH).map(move |dy| (x + dx, y + dy)))
+                    {
+                        if px < 0 || py < 0 || px >= width || py >= height {
+                            continue;
+                        }
+                        let index = (py * width + px) as usize;
+                        if last_track[index] != track_index {
+                            last_track[index] = track_index;
+                            counts[index] += 1;
+                        }
+                    }
+                }
+            }
+        }
+
+        let max_count = counts.iter().copied().max().unwrap_or_default();
+        if max_count == 0 {
+            return;
+        }
+
+        // log scale so a trail ridden a few times still shows up next to one ridden hundreds of times
+        let max_log = (1.0 + max_count as f64).ln();
+        let heat_img = RgbaImage::from_fn(width as u32, height as u32, |x, y| {
+            let count = counts[(y as i32 * width + x as i32) as usize];
+            if count == 0 {
+                return Rgba([0, 0, 0, 0]);
+            }
+
+            let t = (1.0 + count as f64).ln() / max_log;
+            let (r, g, b) = interpolate_color(&HEAT_SCALE, t);
+            Rgba([r, g, b, ((0.4 + 0.6 * t) * 255.0) as u8])
+        });
+
+        match base_map::rgba_to_pixmap(heat_img) {
+            Ok(heat_pixmap) => pixmap.draw_pixmap(
+                0,
+                0,
+                heat_pixmap.as_ref(),
+                &PixmapPaint::default(),
+                Transform::identity(),
+                None,
+            ),
+            Err(e) => tracing::error!("Failed to draw heatmap: {:?}", e),
+        }
+
+        self.draw_outline(bounds, &mut pixmap);
+    }
+}
+
+impl Heatmap {
+    fn draw_outline(&self, bounds: &Bounds, pixmap: &mut PixmapMut) {
+        let mut paint = Paint::default();
+        paint.set_color_rgba8(255, 255, 255, 200);
+        paint.anti_alias = true;
+        let stroke = Stroke {
+            width: 2.0,
+            dash: StrokeDash::new(vec![8.0, 6.0], 0.0),
+            ..Stroke::default()
+        };
+
+        for ring in &self.outline {
+            let mut pb = PathBuilder::new();
+            for (i, (lng, lat)) in ring.iter().enumerate() {
+                let x = bounds.x_to_px(lon_to_x(*lng, bounds.zoom)) as f32;
+                let y = bounds.y_to_px(lat_to_y(*lat, bounds.zoom)) as f32;
+                match i {
+                    0 => pb.move_to(x, y),
+                    _ => pb.line_to(x, y),
+                }
+            }
+
+            if let Some(path) = pb.finish() {
+                pixmap.stroke_path(&path, &paint, &stroke, Transform::identity(), None);
+            }
+        }
+    }
+}
+
+pub async fn render_heatmap(
+    heatmap: Heatmap,
+    size: ImageSize,
+    theme: &Theme,
+    format: OutputFormat,
+) -> anyhow::Result<Vec<u8>> {
+    if heatmap.is_empty() {
+        return Err(anyhow::anyhow!("No rides to draw on the heatmap"));
+    }
+
+    let overlay = Overlay::new(theme, heatmap.extent(0, 0.0));
+    let tools: Vec<MapTool> = vec![Box::new(overlay), Box::new(heatmap)];
+
+    let padding = (20.0 * size.scale()) as u32;
+    let provider = TileProvider::from_env();
+    let (base_img, bounds) = base_map::render_tiles(
+        provider.as_ref(),
+        &tools,
+        size.width,
+        size.height,
+        (padding, padding),
+    )
+    .await;
+
+    // counting every ride pixel by pixel takes a while, so it's kept off the async runtime
+    tokio::task::spawn_blocking(move || {
+        let img = base_map::draw_tools(base_img, &tools, &bounds)?;
+        output::encode(&DynamicImage::ImageRgba8(img), format)
+    })
+    .await?
+}
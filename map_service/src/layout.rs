@@ -0,0 +1,339 @@
+use ab_glyph::{FontArc, PxScale};
+use image::{Rgba, RgbaImage};
+use imageproc::drawing::{draw_text_mut, text_size};
+
+use crate::{TextAlignment, TextOptions};
+
+// line height as a multiple of the font's pixel size
+const LINE_HEIGHT: f32 = 1.05;
+// text lines are wrapped onto at most this many lines before they start shrinking
+const MAX_WRAPPED_LINES: usize = 2;
+// how small a line can shrink to fit, as a fraction of its font size
+const MIN_SHRINK: f32 = 0.5;
+const SHRINK_STEP: f32 = 0.95;
+const ICON_SPACING: f32 = 15.0;
+// blank space left by a spacer, for the 900x900 layout
+const SPACER_HEIGHT: f32 = 80.0;
+
+pub(crate) enum TextElement {
+    Text(String, TextOptions),
+    TextWithSVG {
+        text: String,
+        options: TextOptions,
+        svg_data: Vec<u8>,
+    },
+    Columns(Vec<Column>, TextOptions),
+    Spacer,
+}
+
+// one cell of a multi-column stat row, the row's width is split evenly between its columns
+#[derive(Debug, Clone)]
+pub struct Column {
+    pub text: String,
+    pub svg_data: Option<Vec<u8>>,
+}
+
+impl Column {
+    pub fn new(text: &str) -> Self {
+        Column {
+            text: text.to_owned(),
+            svg_data: None,
+        }
+    }
+
+    pub fn with_svg(mut self, svg_data: &[u8]) -> Self {
+        self.svg_data = Some(svg_data.to_vec());
+        self
+    }
+}
+
+// the part of the image text can go in, anything outside of it is margin
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct SafeArea {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+enum DrawOp {
+    Text {
+        text: String,
+        x: i32,
+        y: i32,
+        px: f32,
+        color: Rgba<u8>,
+    },
+    Icon {
+        svg_data: Vec<u8>,
+        x: i32,
+        y: i32,
+        px: f32,
+    },
+}
+
+// a laid out element, y positions are relative to the top of the row
+struct Row {
+    height: i32,
+    ops: Vec<DrawOp>,
+}
+
+// measures, wraps and shrinks the elements so they fit the safe area, then draws them centered vertically in it
+// scale is the image's scale from the 900x900 layout
+pub(crate) fn draw_elements(
+    img: &mut RgbaImage,
+    font: &FontArc,
+    elements: &[TextElement],
+    area: SafeArea,
+    scale: f32,
+    text_color: Rgba<u8>,
+) {
+    // shrink the whole block until it fits the height of the area
+    let mut fit = 1.0;
+    let rows = loop {
+        let rows: Vec<Row> = elements
+            .iter()
+            .map(|element| layout_element(element, font, area, scale * fit, text_color))
+            .collect();
+
+        let total_height: i32 = rows.iter().map(|row| row.height).sum();
+        if total_height <= area.height || fit * SHRINK_STEP < MIN_SHRINK {
+            break rows;
+        }
+        fit *= SHRINK_STEP;
+    };
+
+    let total_height: i32 = rows.iter().map(|row| row.height).sum();
+    let mut current_y = area.y + ((area.height - total_height) / 2).max(0);
+
+    for row in rows {
+        for op in row.ops {
+            match op {
+                DrawOp::Text {
+                    text,
+                    x,
+                    y,
+                    px,
+                    color,
+                } => {
+                    draw_text_mut(img, color, x, current_y + y, PxScale::from(px), font, &text);
+                }
+                DrawOp::Icon { svg_data, x, y, px } => {
+                    match crate::svg::render_svg(&svg_data, px) {
+                        Ok(icon) => {
+                            image::imageops::overlay(img, &icon, x.into(), (current_y + y).into())
+                        }
+                        Err(e) => tracing::warn!("Failed to render text icon: {:?}", e),
+                    }
+                }
+            }
+        }
+        current_y += row.height;
+    }
+}
+
+fn layout_element(
+    element: &TextElement,
+    font: &FontArc,
+    area: SafeArea,
+    scale: f32,
+    text_color: Rgba<u8>,
+) -> Row {
+    match element {
+        TextElement::Text(text, options) => {
+            layout_text(text, options, font, area, scale, text_color)
+        }
+        TextElement::TextWithSVG {
+            text,
+            options,
+            svg_data,
+        } => {
+            let column = Column {
+                text: text.clone(),
+                svg_data: Some(svg_data.clone()),
+            };
+            layout_columns(
+                std::slice::from_ref(&column),
+                options,
+                font,
+                area,
+                scale,
+                text_color,
+            )
+        }
+        TextElement::Columns(columns, options) => {
+            layout_columns(columns, options, font, area, scale, text_color)
+        }
+        TextElement::Spacer => Row {
+            height: (SPACER_HEIGHT * scale) as i32,
+            ops: Vec::new(),
+        },
+    }
+}
+
+// wraps onto a second line before shrinking, long ride names are common
+fn layout_text(
+    text: &str,
+    options: &TextOptions,
+    font: &FontArc,
+    area: SafeArea,
+    scale: f32,
+    text_color: Rgba<u8>,
+) -> Row {
+    let max_px = options.font_size * 2.0 * scale;
+    let mut px = max_px;
+    let lines = loop {
+        let lines = wrap(text, font, px, area.width);
+        let fits = lines.len() <= MAX_WRAPPED_LINES
+            && lines
+                .iter()
+                .all(|line| measure(font, px, line) <= area.width);
+        if fits || px * SHRINK_STEP < max_px * MIN_SHRINK {
+            break lines;
+        }
+        px *= SHRINK_STEP;
+    };
+
+    let line_height = (px * LINE_HEIGHT) as i32;
+    let ops = lines
+        .into_iter()
+        .enumerate()
+        .map(|(i, line)| {
+            let width = measure(font, px, &line);
+            DrawOp::Text {
+                x: align(options.alignment, area.x, area.width, width),
+                y: i as i32 * line_height,
+                text: line,
+                px,
+                color: options.color.unwrap_or(text_color),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    Row {
+        height: ops.len() as i32 * line_height,
+        ops,
+    }
+}
+
+// every column shares one font size, shrunk until the widest column fits
+fn layout_columns(
+    columns: &[Column],
+    options: &TextOptions,
+    font: &FontArc,
+    area: SafeArea,
+    scale: f32,
+    text_color: Rgba<u8>,
+) -> Row {
+    if columns.is_empty() {
+        return Row {
+            height: 0,
+            ops: Vec::new(),
+        };
+    }
+
+    let column_width = area.width / columns.len() as i32;
+    let icon_aspects: Vec<Option<f32>> = columns
+        .iter()
+        .map(|column| column.svg_data.as_deref().and_then(icon_aspect))
+        .collect();
+
+    let column_size = |column: &Column, icon_aspect: Option<f32>, px: f32| {
+        let icon_width = match icon_aspect {
+            Some(aspect) => (px * aspect + ICON_SPACING * scale) as i32,
+            None => 0,
+        };
+        (icon_width, icon_width + measure(font, px, &column.text))
+    };
+
+    let max_px = options.font_size * 2.0 * scale;
+    let mut px = max_px;
+    while px * SHRINK_STEP >= max_px * MIN_SHRINK
+        && columns
+            .iter()
+            .zip(&icon_aspects)
+            .any(|(column, aspect)| column_size(column, *aspect, px).1 > column_width)
+    {
+        px *= SHRINK_STEP;
+    }
+
+    let mut ops = Vec::new();
+    for (i, (column, aspect)) in columns.iter().zip(&icon_aspects).enumerate() {
+        let (icon_width, total_width) = column_size(column, *aspect, px);
+        let x = align(
+            options.alignment,
+            area.x + i as i32 * column_width,
+            column_width,
+            total_width,
+        );
+
+        if let (Some(svg_data), Some(_)) = (&column.svg_data, aspect) {
+            ops.push(DrawOp::Icon {
+                svg_data: svg_data.clone(),
+                x,
+                y: 0,
+                px,
+            });
+        }
+        ops.push(DrawOp::Text {
+            text: column.text.clone(),
+            x: x + icon_width,
+            y: 0,
+            px,
+            color: options.color.unwrap_or(text_color),
+        });
+    }
+
+    Row {
+        height: (px * LINE_HEIGHT) as i32,
+        ops,
+    }
+}
+
+// greedy word wrap, a single word wider than max_width gets a line of its own
+fn wrap(text: &str, font: &FontArc, px: f32, max_width: i32) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    let mut current = String::new();
+
+    for word in text.split_whitespace() {
+        let candidate = match current.is_empty() {
+            true => word.to_owned(),
+            false => format!("{current} {word}"),
+        };
+
+        if current.is_empty() || measure(font, px, &candidate) <= max_width {
+            current = candidate;
+        } else {
+            lines.push(std::mem::replace(&mut current, word.to_owned()));
+        }
+    }
+
+    if !current.is_empty() || lines.is_empty() {
+        lines.push(current);
+    }
+    lines
+}
+
+fn measure(font: &FontArc, px: f32, text: &str) -> i32 {
+    text_size(PxScale::from(px), font, text).0 as i32
+}
+
+fn align(alignment: TextAlignment, x: i32, available_width: i32, width: i32) -> i32 {
+    match alignment {
+        TextAlignment::Left => x,
+        TextAlignment::Center => x + (available_width - width) / 2,
+        TextAlignment::Right => x + available_width - width,
+    }
+}
+
+// width over height of an svg icon, None if it can't be parsed
+fn icon_aspect(svg_data: &[u8]) -> Option<f32> {
+    match crate::svg::parse_svg(svg_data, 16.0) {
+        Ok(tree) if tree.size().height() > 0.0 => Some(tree.size().width() / tree.size().height()),
+        Ok(_) => None,
+        Err(e) => {
+            tracing::warn!("Failed to parse text icon: {:?}", e);
+            None
+        }
+    }
+}
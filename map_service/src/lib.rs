@@ -1,49 +1,59 @@
-use std::io::Cursor;
-
-use ab_glyph::{FontRef, PxScale};
+use ab_glyph::FontArc;
 use geo_types::LineString;
-use image::{load_from_memory, DynamicImage, ImageFormat, Rgba, RgbaImage};
-use imageproc::drawing::{draw_text_mut, text_size};
-use shared_lib::env_utils;
+use image::{DynamicImage, Rgba, RgbaImage};
+use staticmap::tools::LineBuilder;
 use staticmap::tools::Tool;
 use staticmap::Bounds;
-use staticmap::{tools::LineBuilder, StaticMapBuilder};
-use tiny_skia::{PixmapMut, Transform};
-
-const IMAGE_WIDTH: u32 = 900;
-const IMAGE_HEIGHT: u32 = 900;
-
-#[derive(Debug, Copy, Clone)]
-pub enum DefaultColor {
-    White,
-    Orange,
-    Blue,
-    Red,
-    Green,
-}
-
-impl From<DefaultColor> for Rgba<u8> {
-    fn from(val: DefaultColor) -> Self {
-        match val {
-            DefaultColor::White => Rgba([255, 255, 255, 255]),
-            DefaultColor::Orange => Rgba([255, 165, 0, 255]),
-            DefaultColor::Blue => Rgba([0, 0, 255, 255]),
-            DefaultColor::Red => Rgba([255, 0, 0, 255]),
-            DefaultColor::Green => Rgba([0, 128, 0, 255]),
-        }
-    }
+use tiny_skia::{
+    GradientStop, LinearGradient, Paint, PixmapMut, Point, Rect, SpreadMode, Transform,
+};
+
+use crate::base_map::MapTool;
+use crate::chart::{ChartArea, ProfileData};
+use crate::colored_route::{ColoredRoute, Legend, RouteStyle};
+use crate::layout::{Column, SafeArea, TextElement};
+use crate::markers::Marker;
+use crate::output::{ImageSize, OutputFormat};
+use crate::theme::{LogoPosition, Theme, ThemeColor};
+use crate::tiles::TileProvider;
+use shared_lib::strava_structs::ActivityStreams;
+
+pub mod base_map;
+pub mod chart;
+pub mod colored_route;
+pub mod heatmap;
+pub mod layout;
+pub mod markers;
+pub mod output;
+pub mod route_svg;
+pub mod svg;
+pub mod theme;
+pub mod tile_cache;
+pub mod tiles;
+
+// sizes for the 900x900 layout, scaled for other image sizes
+const PROFILE_HEIGHT: f32 = 170.0;
+// space kept clear above and below the text
+const VERTICAL_MARGIN: f32 = 30.0;
+// space between the logo and the edges of the image
+const LOGO_MARGIN: f32 = 20.0;
+
+pub(crate) fn load_font() -> anyhow::Result<FontArc> {
+    let font_data = include_bytes!("../assets/PTSans-Bold.ttf");
+    Ok(FontArc::try_from_slice(font_data)?)
 }
 
 #[derive(Debug, Copy, Clone)]
 pub enum TextAlignment {
     Center,
     Left,
+    Right,
 }
 
-// Add to TextOptions
 #[derive(Debug, Clone)]
 pub struct TextOptions {
-    pub color: DefaultColor,
+    // the theme's text color when not set
+    pub color: Option<Rgba<u8>>,
     pub font_size: f32,
     pub alignment: TextAlignment,
 }
@@ -51,112 +61,183 @@ pub struct TextOptions {
 impl Default for TextOptions {
     fn default() -> Self {
         Self {
-            color: DefaultColor::White,
+            color: None,
             font_size: 38.0,
             alignment: TextAlignment::Center,
         }
     }
 }
 
-struct Darken {
+// covers the map so the text stands out, either a flat color or a top to bottom gradient
+pub(crate) struct Overlay {
+    color: ThemeColor,
     opacity: f32,
+    gradient: Option<(f32, f32)>,
     extent: (f64, f64, f64, f64),
 }
 
-impl Tool for Darken {
+impl Overlay {
+    pub(crate) fn new(theme: &Theme, extent: (f64, f64, f64, f64)) -> Self {
+        Overlay {
+            color: theme.overlay_color,
+            opacity: theme.overlay_opacity,
+            gradient: theme.overlay_gradient,
+            extent,
+        }
+    }
+}
+
+impl Tool for Overlay {
     fn extent(&self, _: u8, _: f64) -> (f64, f64, f64, f64) {
         self.extent
     }
 
     fn draw(&self, _bounds: &Bounds, mut pixmap: PixmapMut) {
-        let mut cover_pixmap = tiny_skia::Pixmap::new(IMAGE_WIDTH, IMAGE_HEIGHT).unwrap();
-        let mut cover_pixmap = cover_pixmap.as_mut();
-        cover_pixmap.fill(tiny_skia::Color::from_rgba8(0, 0, 0, 255));
-        let cover_pixmap = cover_pixmap.as_ref();
-
-        let paint = tiny_skia::PixmapPaint {
-            opacity: self.opacity,
-            blend_mode: tiny_skia::BlendMode::default(),
-            quality: tiny_skia::FilterQuality::Nearest,
+        let (width, height) = (pixmap.width() as f32, pixmap.height() as f32);
+        let [r, g, b, a] = self.color.0;
+        let color_at = |opacity: f32| {
+            tiny_skia::Color::from_rgba8(r, g, b, (a as f32 * opacity.clamp(0.0, 1.0)) as u8)
         };
 
-        pixmap.draw_pixmap(0, 0, cover_pixmap, &paint, Transform::default(), None);
+        let mut paint = Paint::default();
+        paint.set_color(color_at(self.opacity));
+        if let Some((top, bottom)) = self.gradient {
+            let gradient = LinearGradient::new(
+                Point::from_xy(0.0, 0.0),
+                Point::from_xy(0.0, height),
+                vec![
+                    GradientStop::new(0.0, color_at(top)),
+                    GradientStop::new(1.0, color_at(bottom)),
+                ],
+                SpreadMode::Pad,
+                Transform::identity(),
+            );
+            if let Some(gradient) = gradient {
+                paint.shader = gradient;
+            }
+        }
+
+        if let Some(rect) = Rect::from_xywh(0.0, 0.0, width, height) {
+            pixmap.fill_rect(rect, &paint, Transform::identity(), None);
+        }
     }
 }
 
-enum TextElement {
-    Text(String, TextOptions),
-    TextWithSVG {
-        text: String,
-        options: TextOptions,
-        svg_data: Vec<u8>,
-    },
-    Spacer,
+// how the route is drawn, a colored route needs the activity's streams
+#[derive(Debug, Clone, Default)]
+pub struct MapOptions {
+    pub route_style: RouteStyle,
+    pub streams: Option<ActivityStreams>,
+    pub show_start_end: bool,
+    // extra markers, e.g. trailheads or waypoints
+    pub markers: Vec<Marker>,
+    pub size: ImageSize,
+    pub theme: Theme,
 }
 
 pub struct MapImage {
     dynamic_img: DynamicImage,
-    font: FontRef<'static>,
+    font: FontArc,
     elements: Vec<TextElement>,
+    profile: Option<ProfileData>,
+    legend: Option<Legend>,
+    size: ImageSize,
+    theme: Theme,
 }
 
 impl MapImage {
-    pub fn new(polyline: &str) -> anyhow::Result<Self> {
-        let font = {
-            let font_data = include_bytes!("../assets/PTSans-Bold.ttf");
-            FontRef::try_from_slice(font_data)?
-        };
+    pub async fn new(polyline: &str) -> anyhow::Result<Self> {
+        Self::with_options(polyline, MapOptions::default()).await
+    }
+
+    pub async fn with_options(polyline: &str, options: MapOptions) -> anyhow::Result<Self> {
+        let theme = options.theme;
+        let font = theme.font()?;
+        let size = options.size;
+        let scale = size.scale();
+
+        let colored_route = options.streams.as_ref().and_then(|streams| {
+            // a little wider than a solid route so the colors are easy to see
+            let width = (theme.route_width + 1.0) * scale;
+            ColoredRoute::from_streams(streams, options.route_style, width)
+        });
+        if options.route_style != RouteStyle::Solid && colored_route.is_none() {
+            tracing::debug!(
+                "No stream data for a {:?} route, drawing a solid line",
+                options.route_style
+            );
+        }
+
+        let legend = colored_route.as_ref().map(|route| Legend {
+            style: options.route_style,
+            range: route.range(),
+        });
 
         let dynamic_img = {
             let line_string = polyline::decode_polyline(polyline, 5)?;
-            Self::get_background_image(line_string)?
+
+            let mut markers = options.markers;
+            if options.show_start_end {
+                markers.extend(markers::start_end_markers(&line_string));
+            }
+            for marker in &mut markers {
+                marker.size *= scale;
+            }
+
+            Self::get_background_image(line_string, colored_route, markers, size, &theme).await?
         };
 
         Ok(Self {
             dynamic_img,
             font,
             elements: Vec::new(),
+            profile: None,
+            legend,
+            size,
+            theme,
         })
     }
 
-    fn get_background_image(line_string: LineString) -> anyhow::Result<DynamicImage> {
-        let (lat_values, lng_values): (Vec<f64>, Vec<f64>) =
-            line_string.coords().map(|coord| (coord.y, coord.x)).unzip();
-
-        let url_template = {
-            let key = env_utils::get_thunderforest_api_key()
-                .expect("Failed to get Thunderforest API key");
-            "https://c.tile.thunderforest.com/cycle/{z}/{x}/{y}.png?apikey=".to_string() + &key
+    async fn get_background_image(
+        line_string: LineString,
+        colored_route: Option<ColoredRoute>,
+        markers: Vec<Marker>,
+        size: ImageSize,
+        theme: &Theme,
+    ) -> anyhow::Result<DynamicImage> {
+        let route: MapTool = match colored_route {
+            Some(colored_route) => Box::new(colored_route),
+            None => {
+                let (lat_values, lng_values): (Vec<f64>, Vec<f64>) =
+                    line_string.coords().map(|coord| (coord.y, coord.x)).unzip();
+
+                Box::new(
+                    LineBuilder::default()
+                        .lat_coordinates(lat_values)
+                        .lon_coordinates(lng_values)
+                        .width(theme.route_width * size.scale())
+                        .simplify(true)
+                        .color({
+                            let [r, g, b, a] = theme.route_color.0;
+                            staticmap::tools::Color::new(true, r, g, b, a)
+                        })
+                        .build()?,
+                )
+            }
         };
 
-        let map_png = {
-            let line = LineBuilder::default()
-                .lat_coordinates(lat_values.clone())
-                .lon_coordinates(lng_values.clone())
-                .width(3.)
-                .simplify(true)
-                .color(staticmap::tools::Color::new(true, 255, 165, 0, 255))
-                .build()?;
-
-            let darken = Darken {
-                opacity: 0.65,
-                extent: line.extent(0, 0.0),
-            };
-
-            let mut map = StaticMapBuilder::default()
-                .width(IMAGE_WIDTH)
-                .height(IMAGE_HEIGHT)
-                .padding((5, 0))
-                .url_template(url_template)
-                .build()?;
-
-            map.add_tool(darken);
-            map.add_tool(line);
-            let map_image = map.encode_png()?;
-            load_from_memory(&map_image)?
-        };
+        let overlay = Overlay::new(theme, route.extent(0, 0.0));
 
-        Ok(map_png)
+        let mut tools: Vec<MapTool> = vec![Box::new(overlay), route];
+        for marker in markers {
+            tools.push(Box::new(marker));
+        }
+
+        let provider = TileProvider::from_env();
+        let map_img =
+            base_map::render(provider.as_ref(), &tools, size.width, size.height, (5, 0)).await?;
+
+        Ok(DynamicImage::ImageRgba8(map_img))
     }
 
     pub fn add_text(&mut self, text: &str, options: impl Into<TextOptions>) -> &mut Self {
@@ -179,159 +260,127 @@ impl MapImage {
         self
     }
 
+    // a row of stats side by side, e.g. distance and elevation
+    pub fn add_columns(
+        &mut self,
+        columns: Vec<Column>,
+        options: impl Into<TextOptions>,
+    ) -> &mut Self {
+        self.elements
+            .push(TextElement::Columns(columns, options.into()));
+        self
+    }
+
     pub fn add_spacer(&mut self) -> &mut Self {
         self.elements.push(TextElement::Spacer);
         self
     }
 
-    fn draw_all_text(&mut self) {
-        const LINE_SPACING: i32 = 80;
-        const IIMAGE_WIDTH: i32 = IMAGE_WIDTH as i32;
-        const IIMAGE_HEIGHT: i32 = IMAGE_HEIGHT as i32;
-        const HORIZONTAL_PADDING: i32 = IIMAGE_WIDTH / 8;
-
-        let total_elements = self.elements.len();
-        let total_height = total_elements as i32 * LINE_SPACING;
-        let mut current_y = (IIMAGE_HEIGHT / 2) - (total_height / 2);
-
-        let mut rgba_img = self.dynamic_img.to_rgba8();
-
-        for element in &self.elements {
-            match element {
-                TextElement::Text(text, options) => {
-                    let scale = PxScale {
-                        x: options.font_size * 2.0,
-                        y: options.font_size * 2.0,
-                    };
-
-                    // text dimensions
-                    let (text_width, _) = text_size(scale, &self.font, text);
-
-                    let x = match options.alignment {
-                        TextAlignment::Center => (IIMAGE_WIDTH - text_width as i32) / 2,
-                        TextAlignment::Left => HORIZONTAL_PADDING,
-                    };
-
-                    draw_text_mut(
-                        &mut rgba_img,
-                        options.color.into(),
-                        x,
-                        current_y,
-                        scale,
-                        &self.font,
-                        text,
-                    );
-                    current_y += LINE_SPACING;
-                }
-
-                TextElement::TextWithSVG {
-                    text,
-                    options,
-                    svg_data,
-                } => {
-                    let svg_img = self
-                        .render_svg(svg_data, options.font_size * 2.0)
-                        .expect("Failed to render SVG");
-
-                    let scale = PxScale {
-                        x: options.font_size * 2.0,
-                        y: options.font_size * 2.0,
-                    };
-
-                    // combined width
-                    let (text_width, _) = text_size(scale, &self.font, text);
-
-                    let spacing = 15;
-
-                    let total_width = svg_img.width() as i32 + spacing + text_width as i32;
-
-                    let start_x = match options.alignment {
-                        TextAlignment::Center => (IIMAGE_WIDTH - total_width) / 2,
-                        TextAlignment::Left => HORIZONTAL_PADDING,
-                    };
-
-                    image::imageops::overlay(
-                        &mut rgba_img,
-                        &svg_img,
-                        start_x as i64,
-                        current_y as i64,
-                    );
-
-                    draw_text_mut(
-                        &mut rgba_img,
-                        options.color.into(),
-                        start_x + svg_img.width() as i32 + spacing,
-                        current_y,
-                        scale,
-                        &self.font,
-                        text,
-                    );
-
-                    current_y += LINE_SPACING;
-                }
-
-                TextElement::Spacer => {
-                    current_y += LINE_SPACING;
-                }
-            }
-        }
-
-        self.dynamic_img = DynamicImage::ImageRgba8(rgba_img);
+    // draws the profile along the bottom of the image, the text moves up to make room for it
+    pub fn add_elevation_profile(&mut self, profile: ProfileData) -> &mut Self {
+        self.profile = Some(profile);
+        self
     }
 
-    fn render_svg(&self, svg_data: &[u8], target_height: f32) -> anyhow::Result<RgbaImage> {
-        let opt = usvg::Options {
-            resources_dir: None,
-            font_family: "Arial".to_string(),
-            font_size: target_height,
-            ..usvg::Options::default()
+    fn draw_profile(&self, img: &mut RgbaImage) {
+        let profile = match &self.profile {
+            Some(profile) => profile,
+            None => return,
         };
 
-        let tree = usvg::Tree::from_data(svg_data, &opt)
-            .map_err(|e| anyhow::anyhow!("SVG parse error: {}", e))?;
-
-        let (_w, h) = tree.size().to_int_size().dimensions();
-        let scale = if h > 0 { target_height / h as f32 } else { 2.0 };
-
-        let pixmap_size = tree
-            .size()
-            .to_int_size()
-            .scale_by(scale)
-            .expect("Invalid SVG dimensions");
+        let profile_height = self.profile_height();
+        let area = ChartArea {
+            x: 0,
+            y: self.size.height - profile_height,
+            width: self.size.width,
+            height: profile_height - 10,
+        };
 
-        let pixmap = {
-            let mut pixmap = tiny_skia::Pixmap::new(pixmap_size.width(), pixmap_size.height())
-                .ok_or_else(|| anyhow::anyhow!("Invalid SVG dimensions"))?;
+        if let Err(e) = chart::draw_profile(img, &self.font, profile, area, &self.theme) {
+            tracing::warn!("Failed to draw elevation profile: {:?}", e);
+        }
+    }
 
-            resvg::render(
-                &tree,
-                tiny_skia::Transform::from_scale(scale, scale),
-                &mut pixmap.as_mut(),
-            );
+    fn profile_height(&self) -> u32 {
+        (PROFILE_HEIGHT * self.size.scale()) as u32
+    }
 
-            pixmap
+    fn draw_all_text(&self, img: &mut RgbaImage) {
+        let scale = self.size.scale();
+        let horizontal_margin = self.size.width as i32 / 8;
+        let vertical_margin = (VERTICAL_MARGIN * scale) as i32;
+
+        let available_height = match self.profile {
+            Some(_) => self.size.height - self.profile_height(),
+            None => self.size.height,
+        } as i32;
+
+        let area = SafeArea {
+            x: horizontal_margin,
+            y: vertical_margin,
+            width: self.size.width as i32 - horizontal_margin * 2,
+            height: available_height - vertical_margin * 2,
         };
 
-        let mut img_data = pixmap.data().to_vec();
-        for pixel in img_data.chunks_exact_mut(4) {
-            let a = pixel[3] as f32 / 255.0;
-            if a > 0.0 {
-                pixel[0] = (pixel[0] as f32 / a).min(255.0) as u8;
-                pixel[1] = (pixel[1] as f32 / a).min(255.0) as u8;
-                pixel[2] = (pixel[2] as f32 / a).min(255.0) as u8;
-            }
+        layout::draw_elements(
+            img,
+            &self.font,
+            &self.elements,
+            area,
+            scale,
+            self.theme.text_color.into(),
+        );
+    }
+
+    fn draw_legend(&self, img: &mut RgbaImage) {
+        if let Some(legend) = &self.legend {
+            legend.draw(img, &self.font, self.theme.text_color.into());
         }
+    }
+
+    pub fn encode_png(&self) -> anyhow::Result<Vec<u8>> {
+        self.encode(OutputFormat::Png)
+    }
+
+    // the overlays are drawn onto a copy of the map, so the same image can be encoded more than once
+    pub fn encode(&self, format: OutputFormat) -> anyhow::Result<Vec<u8>> {
+        let mut img = self.dynamic_img.to_rgba8();
+        self.draw_legend(&mut img);
+        self.draw_profile(&mut img);
+        self.draw_all_text(&mut img);
+        self.draw_logo(&mut img);
 
-        RgbaImage::from_raw(pixmap.width(), pixmap.height(), img_data)
-            .ok_or_else(|| anyhow::anyhow!("Failed to create image buffer"))
+        output::encode(&DynamicImage::ImageRgba8(img), format)
     }
 
-    pub fn encode_png(&mut self) -> anyhow::Result<Vec<u8>> {
-        self.draw_all_text();
-        let mut output_bytes = Vec::new();
-        self.dynamic_img
-            .write_to(&mut Cursor::new(&mut output_bytes), ImageFormat::Png)?;
+    fn draw_logo(&self, img: &mut RgbaImage) {
+        let scale = self.size.scale();
+        let logo = match self.theme.render_logo(self.theme.logo_height * scale) {
+            Some(logo) => logo,
+            None => return,
+        };
+
+        let margin = (LOGO_MARGIN * scale) as i64;
+        let right = self.size.width as i64 - logo.width() as i64 - margin;
+        // keep clear of the legend in the top left and the profile along the bottom
+        let top = match (self.theme.logo_position, &self.legend) {
+            (LogoPosition::TopLeft, Some(_)) => margin + (100.0 * scale) as i64,
+            _ => margin,
+        };
+        let bottom = match self.profile {
+            Some(_) => self.size.height - self.profile_height(),
+            None => self.size.height,
+        } as i64
+            - logo.height() as i64
+            - margin;
+
+        let (x, y) = match self.theme.logo_position {
+            LogoPosition::TopLeft => (margin, top),
+            LogoPosition::TopRight => (right, top),
+            LogoPosition::BottomLeft => (margin, bottom),
+            LogoPosition::BottomRight => (right, bottom),
+        };
 
-        Ok(output_bytes)
+        image::imageops::overlay(img, &logo, x, y);
     }
 }
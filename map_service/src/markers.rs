@@ -0,0 +1,202 @@
+use std::sync::LazyLock;
+
+use geo_types::LineString;
+use serde::Deserialize;
+use shared_lib::trail_structs::TrailSystem;
+use staticmap::tools::Tool;
+use staticmap::{lat_to_y, lon_to_x, Bounds};
+use tiny_skia::{PixmapMut, Transform};
+
+use crate::theme::ThemeColor;
+
+const DEFAULT_MARKER_SIZE: f32 = 30.0;
+
+static MARKER_ICONS: LazyLock<MarkerIcons> = LazyLock::new(MarkerIcons::load);
+
+// svg files to use instead of the built in marker icons, from the `map_markers` section of the config file
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+struct MarkerIconPaths {
+    start: Option<String>,
+    end: Option<String>,
+    trailhead: Option<String>,
+    waypoint: Option<String>,
+}
+
+struct MarkerIcons {
+    start: Vec<u8>,
+    end: Vec<u8>,
+    trailhead: Vec<u8>,
+    waypoint: Vec<u8>,
+}
+
+impl MarkerIcons {
+    fn load() -> Self {
+        let paths: MarkerIconPaths = shared_lib::config::load_section("map_markers");
+
+        let load_icon = |path: Option<String>, default: &[u8]| match path {
+            Some(path) => std::fs::read(&path).unwrap_or_else(|e| {
+                tracing::error!("Failed to read marker icon {}: {}", path, e);
+                default.to_vec()
+            }),
+            None => default.to_vec(),
+        };
+
+        MarkerIcons {
+            start: load_icon(paths.start, include_bytes!("../assets/markers/start.svg")),
+            end: load_icon(paths.end, include_bytes!("../assets/markers/end.svg")),
+            trailhead: load_icon(
+                paths.trailhead,
+                include_bytes!("../assets/markers/trailhead.svg"),
+            ),
+            waypoint: load_icon(
+                paths.waypoint,
+                include_bytes!("../assets/markers/waypoint.svg"),
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MarkerKind {
+    Start,
+    End,
+    Trailhead,
+    Waypoint,
+}
+
+impl MarkerKind {
+    pub fn icon(&self) -> &'static [u8] {
+        let icons = &*MARKER_ICONS;
+        match self {
+            MarkerKind::Start => &icons.start,
+            MarkerKind::End => &icons.end,
+            MarkerKind::Trailhead => &icons.trailhead,
+            MarkerKind::Waypoint => &icons.waypoint,
+        }
+    }
+
+    // the color of the built in icon, for drawings that use plain dots instead of icons
+    pub fn color(&self) -> ThemeColor {
+        match self {
+            MarkerKind::Start => ThemeColor::rgba(38, 200, 120, 255),
+            MarkerKind::End => ThemeColor::rgba(230, 40, 40, 255),
+            MarkerKind::Trailhead => ThemeColor::rgba(47, 107, 58, 255),
+            MarkerKind::Waypoint => ThemeColor::rgba(49, 130, 255, 255),
+        }
+    }
+}
+
+// an svg icon centered on a point on the map
+#[derive(Debug, Clone)]
+pub struct Marker {
+    pub kind: MarkerKind,
+    pub lat: f64,
+    pub lng: f64,
+    // overrides the icon for the marker kind
+    pub icon: Option<Vec<u8>>,
+    pub size: f32,
+}
+
+impl Marker {
+    pub fn new(kind: MarkerKind, lat: f64, lng: f64) -> Self {
+        Marker {
+            kind,
+            lat,
+            lng,
+            icon: None,
+            size: DEFAULT_MARKER_SIZE,
+        }
+    }
+
+    pub fn with_icon(mut self, svg_data: &[u8]) -> Self {
+        self.icon = Some(svg_data.to_vec());
+        self
+    }
+}
+
+impl Tool for Marker {
+    fn extent(&self, _: u8, _: f64) -> (f64, f64, f64, f64) {
+        (self.lng, self.lat, self.lng, self.lat)
+    }
+
+    fn draw(&self, bounds: &Bounds, mut pixmap: PixmapMut) {
+        let svg_data = match &self.icon {
+            Some(icon) => icon.as_slice(),
+            None => self.kind.icon(),
+        };
+
+        let tree = match crate::svg::parse_svg(svg_data, self.size) {
+            Ok(tree) => tree,
+            Err(e) => {
+                tracing::warn!("Failed to draw {:?} marker: {:?}", self.kind, e);
+                return;
+            }
+        };
+
+        let scale = crate::svg::scale_to_height(&tree, self.size);
+        let width = tree.size().width() * scale;
+        let x = bounds.x_to_px(lon_to_x(self.lng, bounds.zoom)) as f32 - width / 2.0;
+        let y = bounds.y_to_px(lat_to_y(self.lat, bounds.zoom)) as f32 - self.size / 2.0;
+
+        resvg::render(
+            &tree,
+            Transform::from_scale(scale, scale).post_translate(x, y),
+            &mut pixmap,
+        );
+    }
+}
+
+// markers for where the route starts and ends
+pub fn start_end_markers(line_string: &LineString) -> Vec<Marker> {
+    let (first, last) = match (line_string.0.first(), line_string.0.last()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return Vec::new(),
+    };
+
+    vec![
+        Marker::new(MarkerKind::Start, first.y, first.x),
+        Marker::new(MarkerKind::End, last.y, last.x),
+    ]
+}
+
+// trailheads within max_distance meters of the route, closest first
+pub fn nearest_trailheads(
+    trails: &[TrailSystem],
+    polyline: &str,
+    max_distance: f64,
+    limit: usize,
+) -> Vec<Marker> {
+    let line_string = match polyline::decode_polyline(polyline, 5) {
+        Ok(line_string) => line_string,
+        Err(e) => {
+            tracing::warn!("Failed to decode polyline for trailhead markers: {}", e);
+            return Vec::new();
+        }
+    };
+
+    let mut nearby: Vec<(f64, &TrailSystem)> = trails
+        .iter()
+        .filter_map(|trail| {
+            let distance = line_string
+                .coords()
+                .filter_map(|coord| {
+                    shared_lib::utils::haversine_distance(
+                        geo_types::Point::new(trail.lng, trail.lat),
+                        geo_types::Point::from(*coord),
+                    )
+                    .ok()
+                })
+                .fold(f64::MAX, f64::min);
+
+            (distance <= max_distance).then_some((distance, trail))
+        })
+        .collect();
+
+    nearby.sort_by(|a, b| a.0.total_cmp(&b.0));
+    nearby
+        .into_iter()
+        .take(limit)
+        .map(|(_, trail)| Marker::new(MarkerKind::Trailhead, trail.lat, trail.lng))
+        .collect()
+}
@@ -0,0 +1,130 @@
+use std::io::Cursor;
+
+use image::codecs::jpeg::JpegEncoder;
+use image::{DynamicImage, ImageFormat};
+
+// the layout was designed at 900x900, everything else is scaled from there
+const BASE_SIZE: f32 = 900.0;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ImageSize {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl ImageSize {
+    // feed post
+    pub const SQUARE: ImageSize = ImageSize::new(900, 900);
+    pub const STORY: ImageSize = ImageSize::new(1080, 1920);
+    pub const OPEN_GRAPH: ImageSize = ImageSize::new(1200, 630);
+
+    pub const fn new(width: u32, height: u32) -> Self {
+        ImageSize { width, height }
+    }
+
+    // how much bigger or smaller than the 900x900 layout this size is, based on the shorter side
+    pub fn scale(&self) -> f32 {
+        self.width.min(self.height) as f32 / BASE_SIZE
+    }
+
+    pub fn name(&self) -> String {
+        match *self {
+            ImageSize::SQUARE => "square".to_string(),
+            ImageSize::STORY => "story".to_string(),
+            ImageSize::OPEN_GRAPH => "og".to_string(),
+            ImageSize { width, height } => format!("{width}x{height}"),
+        }
+    }
+}
+
+impl Default for ImageSize {
+    fn default() -> Self {
+        ImageSize::SQUARE
+    }
+}
+
+// a preset name or <width>x<height>, anything else is the square preset
+impl From<&str> for ImageSize {
+    fn from(value: &str) -> Self {
+        match value.to_lowercase().as_str() {
+            "story" => ImageSize::STORY,
+            "og" | "open_graph" | "opengraph" => ImageSize::OPEN_GRAPH,
+            other => other
+                .split_once('x')
+                .and_then(|(w, h)| Some(ImageSize::new(w.parse().ok()?, h.parse().ok()?)))
+                .filter(|size| {
+                    (100..=4096).contains(&size.width) && (100..=4096).contains(&size.height)
+                })
+                .unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub enum OutputFormat {
+    #[default]
+    Png,
+    // quality from 1 to 100
+    Jpeg {
+        quality: u8,
+    },
+    // quality from 0 to 100, 100 is lossless
+    WebP {
+        quality: f32,
+    },
+}
+
+// "jpeg", "jpg" or "webp" at a quality that's good for the web, anything else is png
+impl From<&str> for OutputFormat {
+    fn from(value: &str) -> Self {
+        match value.to_lowercase().as_str() {
+            "jpeg" | "jpg" => OutputFormat::Jpeg { quality: 85 },
+            "webp" => OutputFormat::WebP { quality: 80.0 },
+            _ => OutputFormat::Png,
+        }
+    }
+}
+
+impl OutputFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            OutputFormat::Png => "image/png",
+            OutputFormat::Jpeg { .. } => "image/jpeg",
+            OutputFormat::WebP { .. } => "image/webp",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Png => "png",
+            OutputFormat::Jpeg { .. } => "jpg",
+            OutputFormat::WebP { .. } => "webp",
+        }
+    }
+}
+
+pub fn encode(img: &DynamicImage, format: OutputFormat) -> anyhow::Result<Vec<u8>> {
+    let mut output_bytes = Vec::new();
+
+    match format {
+        OutputFormat::Png => {
+            img.write_to(&mut Cursor::new(&mut output_bytes), ImageFormat::Png)?;
+        }
+        OutputFormat::Jpeg { quality } => {
+            // jpeg has no alpha channel
+            let encoder = JpegEncoder::new_with_quality(&mut output_bytes, quality.clamp(1, 100));
+            DynamicImage::ImageRgb8(img.to_rgb8()).write_with_encoder(encoder)?;
+        }
+        OutputFormat::WebP { quality } => {
+            let rgba = img.to_rgba8();
+            let encoder = webp::Encoder::from_rgba(rgba.as_raw(), rgba.width(), rgba.height());
+            let encoded = match quality >= 100.0 {
+                true => encoder.encode_lossless(),
+                false => encoder.encode(quality.clamp(0.0, 100.0)),
+            };
+            output_bytes.extend_from_slice(&encoded);
+        }
+    }
+
+    Ok(output_bytes)
+}
@@ -0,0 +1,160 @@
+use std::fmt::Write;
+
+use staticmap::{lat_to_y, lon_to_x};
+
+use crate::markers::{self, Marker};
+use crate::theme::{Theme, ThemeColor};
+
+// for scaling widths and marker sizes, which are set for the 900x900 layout
+const BASE_SIZE: f64 = 900.0;
+
+// a route drawn as a standalone svg, no tiles so nothing is fetched over the network
+#[derive(Debug, Clone)]
+pub struct SvgOptions {
+    pub width: u32,
+    pub height: u32,
+    pub padding: u32,
+    pub show_start_end: bool,
+    pub markers: Vec<Marker>,
+    // a line of text along the bottom
+    pub title: Option<String>,
+    // transparent when not set
+    pub background: Option<ThemeColor>,
+    pub theme: Theme,
+}
+
+impl Default for SvgOptions {
+    fn default() -> Self {
+        SvgOptions {
+            width: 300,
+            height: 300,
+            padding: 10,
+            show_start_end: false,
+            markers: Vec::new(),
+            title: None,
+            background: None,
+            theme: Theme::default(),
+        }
+    }
+}
+
+pub fn render_route_svg(polyline: &str, options: &SvgOptions) -> anyhow::Result<String> {
+    let line_string = polyline::decode_polyline(polyline, 5)?;
+    if line_string.0.is_empty() {
+        return Err(anyhow::anyhow!("Polyline has no points"));
+    }
+
+    let mut markers = options.markers.clone();
+    if options.show_start_end {
+        markers.extend(markers::start_end_markers(&line_string));
+    }
+
+    let (width, height) = (options.width as f64, options.height as f64);
+    let scale = width.min(height) / BASE_SIZE;
+    let padding = options.padding as f64;
+    let title_size = (48.0 * scale).max(10.0);
+    let title_height = match options.title {
+        Some(_) => title_size * 1.4,
+        None => 0.0,
+    };
+
+    // web mercator at zoom 0, the same projection the raster maps use
+    let project = |lng: f64, lat: f64| (lon_to_x(lng, 0), lat_to_y(lat, 0));
+    let points: Vec<(f64, f64)> = line_string
+        .coords()
+        .map(|coord| project(coord.x, coord.y))
+        .collect();
+    let marker_points: Vec<(f64, f64)> = markers
+        .iter()
+        .map(|marker| project(marker.lng, marker.lat))
+        .collect();
+
+    let all_points = || points.iter().chain(&marker_points);
+    let min_x = all_points().map(|p| p.0).fold(f64::MAX, f64::min);
+    let max_x = all_points().map(|p| p.0).fold(f64::MIN, f64::max);
+    let min_y = all_points().map(|p| p.1).fold(f64::MAX, f64::min);
+    let max_y = all_points().map(|p| p.1).fold(f64::MIN, f64::max);
+
+    // fit the route inside the padding, keeping its shape and centering it
+    let available_width = (width - padding * 2.0).max(1.0);
+    let available_height = (height - padding * 2.0 - title_height).max(1.0);
+    let (extent_width, extent_height) = (max_x - min_x, max_y - min_y);
+    let fit = match (extent_width > 0.0, extent_height > 0.0) {
+        (true, true) => (available_width / extent_width).min(available_height / extent_height),
+        (true, false) => available_width / extent_width,
+        (false, true) => available_height / extent_height,
+        (false, false) => 1.0,
+    };
+    let offset_x = padding + (available_width - extent_width * fit) / 2.0;
+    let offset_y = padding + (available_height - extent_height * fit) / 2.0;
+    let to_px = |(x, y): (f64, f64)| ((x - min_x) * fit + offset_x, (y - min_y) * fit + offset_y);
+
+    let mut svg = String::new();
+    writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}">"#,
+        options.width, options.height, options.width, options.height
+    )?;
+
+    if let Some(background) = options.background {
+        writeln!(
+            svg,
+            r#"<rect width="100%" height="100%" fill="{}" fill-opacity="{}"/>"#,
+            background.hex(),
+            background.opacity()
+        )?;
+    }
+
+    let route_points = points
+        .iter()
+        .map(|point| {
+            let (x, y) = to_px(*point);
+            format!("{x:.1},{y:.1}")
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+    let route_color = options.theme.route_color;
+    writeln!(
+        svg,
+        r#"<polyline points="{}" fill="none" stroke="{}" stroke-opacity="{}" stroke-width="{:.1}" stroke-linecap="round" stroke-linejoin="round"/>"#,
+        route_points,
+        route_color.hex(),
+        route_color.opacity(),
+        (options.theme.route_width as f64 * scale).max(1.0)
+    )?;
+
+    for (marker, point) in markers.iter().zip(&marker_points) {
+        let (x, y) = to_px(*point);
+        let radius = (marker.size as f64 / 2.0 * scale).max(1.5);
+        writeln!(
+            svg,
+            r#"<circle cx="{x:.1}" cy="{y:.1}" r="{radius:.1}" fill="{}" stroke="white" stroke-width="{:.1}"/>"#,
+            marker.kind.color().hex(),
+            (radius / 4.0).max(0.5)
+        )?;
+    }
+
+    if let Some(title) = &options.title {
+        let text_color = options.theme.text_color;
+        writeln!(
+            svg,
+            r#"<text x="{:.1}" y="{:.1}" font-family="PT Sans, sans-serif" font-weight="bold" font-size="{:.1}" text-anchor="middle" fill="{}" fill-opacity="{}">{}</text>"#,
+            width / 2.0,
+            height - padding - title_size * 0.3,
+            title_size,
+            text_color.hex(),
+            text_color.opacity(),
+            escape_xml(title)
+        )?;
+    }
+
+    svg.push_str("</svg>\n");
+    Ok(svg)
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
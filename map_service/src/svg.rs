@@ -0,0 +1,50 @@
+use image::RgbaImage;
+
+use crate::base_map::pixmap_to_rgba;
+
+pub fn parse_svg(svg_data: &[u8], font_size: f32) -> anyhow::Result<usvg::Tree> {
+    let opt = usvg::Options {
+        resources_dir: None,
+        font_family: "Arial".to_string(),
+        font_size,
+        ..usvg::Options::default()
+    };
+
+    usvg::Tree::from_data(svg_data, &opt).map_err(|e| anyhow::anyhow!("SVG parse error: {}", e))
+}
+
+// scale that makes the svg the target height
+pub fn scale_to_height(tree: &usvg::Tree, target_height: f32) -> f32 {
+    let (_w, h) = tree.size().to_int_size().dimensions();
+    if h > 0 {
+        target_height / h as f32
+    } else {
+        2.0
+    }
+}
+
+pub fn render_svg(svg_data: &[u8], target_height: f32) -> anyhow::Result<RgbaImage> {
+    let tree = parse_svg(svg_data, target_height)?;
+    let scale = scale_to_height(&tree, target_height);
+
+    let pixmap_size = tree
+        .size()
+        .to_int_size()
+        .scale_by(scale)
+        .ok_or_else(|| anyhow::anyhow!("Invalid SVG dimensions"))?;
+
+    let pixmap = {
+        let mut pixmap = tiny_skia::Pixmap::new(pixmap_size.width(), pixmap_size.height())
+            .ok_or_else(|| anyhow::anyhow!("Invalid SVG dimensions"))?;
+
+        resvg::render(
+            &tree,
+            tiny_skia::Transform::from_scale(scale, scale),
+            &mut pixmap.as_mut(),
+        );
+
+        pixmap
+    };
+
+    pixmap_to_rgba(pixmap)
+}
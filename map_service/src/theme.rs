@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock};
+
+use ab_glyph::FontArc;
+use image::{Rgba, RgbaImage};
+use serde::Deserialize;
+
+static THEMES: LazyLock<HashMap<String, Theme>> = LazyLock::new(|| {
+    let themes: HashMap<String, Theme> = shared_lib::config::load_section("map_themes");
+    themes
+        .into_iter()
+        .map(|(name, theme)| (name, theme.load_assets()))
+        .collect()
+});
+
+// a theme from the `map_themes` section of the config file
+// a theme named "default" replaces the built in one for anything that doesn't pick a theme
+pub fn get_theme(name: Option<&str>) -> Theme {
+    let theme = name.and_then(|name| {
+        let theme = THEMES.get(name);
+        if theme.is_none() {
+            tracing::warn!("No map theme named {}, using the default theme", name);
+        }
+        theme
+    });
+
+    theme
+        .or_else(|| THEMES.get("default"))
+        .cloned()
+        .unwrap_or_default()
+}
+
+// an RGBA color written as "#rrggbb" or "#rrggbbaa" in the config file
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct ThemeColor(pub [u8; 4]);
+
+impl ThemeColor {
+    pub const fn rgba(r: u8, g: u8, b: u8, a: u8) -> Self {
+        ThemeColor([r, g, b, a])
+    }
+
+    // "#rrggbb", without the alpha
+    pub fn hex(&self) -> String {
+        let [r, g, b, _] = self.0;
+        format!("#{r:02x}{g:02x}{b:02x}")
+    }
+
+    pub fn opacity(&self) -> f32 {
+        self.0[3] as f32 / 255.0
+    }
+}
+
+impl TryFrom<String> for ThemeColor {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let hex = value.trim_start_matches('#');
+        let channel = |i: usize| {
+            hex.get(i..i + 2)
+                .and_then(|channel| u8::from_str_radix(channel, 16).ok())
+                .ok_or_else(|| format!("Invalid color: {value}"))
+        };
+
+        match hex.len() {
+            6 => Ok(ThemeColor([channel(0)?, channel(2)?, channel(4)?, 255])),
+            8 => Ok(ThemeColor([
+                channel(0)?,
+                channel(2)?,
+                channel(4)?,
+                channel(6)?,
+            ])),
+            _ => Err(format!("Invalid color: {value}")),
+        }
+    }
+}
+
+impl From<ThemeColor> for Rgba<u8> {
+    fn from(val: ThemeColor) -> Self {
+        Rgba(val.0)
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogoPosition {
+    TopLeft,
+    #[default]
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+// how a map image looks, sizes are for the 900x900 layout and get scaled with the image
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Theme {
+    pub text_color: ThemeColor,
+    pub overlay_color: ThemeColor,
+    pub overlay_opacity: f32,
+    // opacity at the top and bottom of the image, replaces overlay_opacity when set
+    pub overlay_gradient: Option<(f32, f32)>,
+    pub route_color: ThemeColor,
+    pub route_width: f32,
+    // a ttf or otf file, PT Sans Bold when not set
+    pub font_path: Option<String>,
+    // an svg or png file drawn in a corner of the image
+    pub logo_path: Option<String>,
+    pub logo_position: LogoPosition,
+    pub logo_height: f32,
+    #[serde(skip)]
+    font: Option<FontArc>,
+    #[serde(skip)]
+    logo: Option<Arc<Vec<u8>>>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            text_color: ThemeColor::rgba(255, 255, 255, 255),
+            overlay_color: ThemeColor::rgba(0, 0, 0, 255),
+            overlay_opacity: 0.65,
+            overlay_gradient: None,
+            route_color: ThemeColor::rgba(255, 165, 0, 255),
+            route_width: 3.0,
+            font_path: None,
+            logo_path: None,
+            logo_position: LogoPosition::default(),
+            logo_height: 60.0,
+            font: None,
+            logo: None,
+        }
+    }
+}
+
+impl Theme {
+    // reads the font and logo files, a file that can't be loaded is left out
+    pub fn load_assets(mut self) -> Self {
+        if let Some(path) = &self.font_path {
+            let font = std::fs::read(path)
+                .map_err(anyhow::Error::from)
+                .and_then(|data| Ok(FontArc::try_from_vec(data)?));
+            match font {
+                Ok(font) => self.font = Some(font),
+                Err(e) => tracing::error!("Failed to load theme font {}: {:?}", path, e),
+            }
+        }
+
+        if let Some(path) = &self.logo_path {
+            match std::fs::read(path) {
+                Ok(data) => self.logo = Some(Arc::new(data)),
+                Err(e) => tracing::error!("Failed to read theme logo {}: {}", path, e),
+            }
+        }
+
+        self
+    }
+
+    pub fn font(&self) -> anyhow::Result<FontArc> {
+        match &self.font {
+            Some(font) => Ok(font.clone()),
+            None => crate::load_font(),
+        }
+    }
+
+    // the logo scaled to the given height, None when the theme has no logo
+    pub fn render_logo(&self, height: f32) -> Option<RgbaImage> {
+        let data = self.logo.as_ref()?;
+
+        let logo = match image::load_from_memory(data) {
+            Ok(img) => {
+                let width = img.width() as f32 * height / img.height().max(1) as f32;
+                Ok(img
+                    .resize(
+                        width as u32,
+                        height as u32,
+                        image::imageops::FilterType::Lanczos3,
+                    )
+                    .to_rgba8())
+            }
+            // not a raster image, try it as an svg
+            Err(_) => crate::svg::render_svg(data, height),
+        };
+
+        match logo {
+            Ok(logo) => Some(logo),
+            Err(e) => {
+                tracing::warn!("Failed to render theme logo: {:?}", e);
+                None
+            }
+        }
+    }
+}
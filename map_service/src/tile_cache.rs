@@ -0,0 +1,180 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use shared_lib::env_utils;
+
+use crate::tiles::TileProvider;
+
+const DEFAULT_TTL_HOURS: u64 = 30 * 24;
+const DEFAULT_MAX_MB: u64 = 256;
+// pruning walks the whole cache directory, so it only happens after enough new tiles or a while after the last one
+const PRUNE_AFTER_WRITES: u64 = 500;
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+static TILE_CACHE: LazyLock<Option<TileCache>> = LazyLock::new(TileCache::from_env);
+
+// the on-disk tile cache, only enabled when MAP_TILE_CACHE_DIR is set
+pub fn get_tile_cache() -> Option<&'static TileCache> {
+    TILE_CACHE.as_ref()
+}
+
+// downloaded tiles stored as <dir>/<provider>/<z>/<x>/<y>.tile
+// tiles older than the ttl are downloaded again, and the oldest tiles are dropped once the cache is over its size limit
+#[derive(Debug)]
+pub struct TileCache {
+    dir: PathBuf,
+    ttl: Duration,
+    max_bytes: u64,
+    // tiles written since the last prune
+    writes: AtomicU64,
+    last_prune: Mutex<Option<Instant>>,
+}
+
+impl TileCache {
+    fn from_env() -> Option<Self> {
+        let dir = env_utils::get_map_tile_cache_dir()?;
+        let ttl_hours = env_utils::get_map_tile_cache_ttl_hours().unwrap_or(DEFAULT_TTL_HOURS);
+        let max_mb = env_utils::get_map_tile_cache_max_mb().unwrap_or(DEFAULT_MAX_MB);
+
+        tracing::info!(
+            "Caching map tiles in {} for {} hours, up to {} MB",
+            dir,
+            ttl_hours,
+            max_mb
+        );
+
+        Some(TileCache {
+            dir: PathBuf::from(dir),
+            ttl: Duration::from_secs(ttl_hours * 60 * 60),
+            max_bytes: max_mb * 1024 * 1024,
+            writes: AtomicU64::new(0),
+            last_prune: Mutex::new(None),
+        })
+    }
+
+    fn tile_path(&self, provider: &TileProvider, z: u8, x: u32, y: u32) -> PathBuf {
+        self.dir
+            .join(provider.name())
+            .join(z.to_string())
+            .join(x.to_string())
+            .join(format!("{y}.tile"))
+    }
+
+    pub async fn get(&self, provider: &TileProvider, z: u8, x: u32, y: u32) -> Option<Vec<u8>> {
+        let path = self.tile_path(provider, z, x, y);
+
+        let modified = tokio::fs::metadata(&path).await.ok()?.modified().ok()?;
+        if self.is_expired(modified) {
+            tracing::trace!("Cached tile {:?} has expired", path);
+            return None;
+        }
+
+        tokio::fs::read(&path).await.ok()
+    }
+
+    pub async fn put(&self, provider: &TileProvider, z: u8, x: u32, y: u32, data: &[u8]) {
+        let path = self.tile_path(provider, z, x, y);
+
+        let result = async {
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            tokio::fs::write(&path, data).await
+        }
+        .await;
+
+        match result {
+            Ok(()) => {
+                self.writes.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(e) => tracing::warn!("Failed to cache tile {:?}: {}", path, e),
+        }
+    }
+
+    fn is_expired(&self, modified: SystemTime) -> bool {
+        modified.elapsed().unwrap_or_default() > self.ttl
+    }
+
+    // prunes once enough tiles have been written since the last prune, or once the interval has
+    // passed with any new tiles at all
+    pub async fn prune_if_needed(&'static self) {
+        let writes = self.writes.load(Ordering::Relaxed);
+        if writes == 0 {
+            return;
+        }
+
+        {
+            let mut last_prune = self.last_prune.lock().unwrap();
+            let interval_passed =
+                last_prune.is_none_or(|last_prune| last_prune.elapsed() >= PRUNE_INTERVAL);
+            if writes < PRUNE_AFTER_WRITES && !interval_passed {
+                return;
+            }
+            *last_prune = Some(Instant::now());
+        }
+
+        self.writes.fetch_sub(writes, Ordering::Relaxed);
+        self.prune().await;
+    }
+
+    // removes expired tiles, then the oldest tiles until the cache fits in its size limit
+    pub async fn prune(&'static self) {
+        let result = tokio::task::spawn_blocking(move || {
+            let mut tiles = Vec::new();
+            collect_files(&self.dir, &mut tiles);
+
+            let mut total_bytes: u64 = 0;
+            let mut kept = Vec::new();
+            for (path, size, modified) in tiles {
+                if self.is_expired(modified) {
+                    let _ = std::fs::remove_file(&path);
+                } else {
+                    total_bytes += size;
+                    kept.push((path, size, modified));
+                }
+            }
+
+            if total_bytes <= self.max_bytes {
+                return;
+            }
+
+            kept.sort_by_key(|(_, _, modified)| *modified);
+            for (path, size, _) in kept {
+                if total_bytes <= self.max_bytes {
+                    break;
+                }
+                if std::fs::remove_file(&path).is_ok() {
+                    total_bytes -= size;
+                }
+            }
+        })
+        .await;
+
+        if let Err(e) = result {
+            tracing::error!("Failed to prune the tile cache: {:?}", e);
+        }
+    }
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<(PathBuf, u64, SystemTime)>) {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let metadata = match entry.metadata() {
+            Ok(metadata) => metadata,
+            Err(_) => continue,
+        };
+
+        if metadata.is_dir() {
+            collect_files(&path, files);
+        } else if let Ok(modified) = metadata.modified() {
+            files.push((path, metadata.len(), modified));
+        }
+    }
+}
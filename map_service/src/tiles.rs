@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock};
+
+use shared_lib::env_utils;
+
+use crate::tile_cache;
+
+static HTTP_CLIENT: LazyLock<reqwest::Client> = LazyLock::new(|| {
+    reqwest::Client::builder()
+        .user_agent("troyonthetrails.com map renderer")
+        .timeout(std::time::Duration::from_secs(10))
+        .build()
+        .expect("Failed to build tile http client")
+});
+
+// opened mbtiles files, keyed by path
+static MBTILES: LazyLock<tokio::sync::Mutex<HashMap<PathBuf, Arc<libsql::Database>>>> =
+    LazyLock::new(|| tokio::sync::Mutex::new(HashMap::new()));
+
+// where map tiles come from, picked with MAP_TILE_PROVIDER:
+//   thunderforest[:style]  thunderforest (cycle style by default), needs THUNDERFOREST_API_KEY
+//   osm                    openstreetmap's standard tiles
+//   xyz:<url template>     any tile server, e.g. xyz:https://tiles.example.com/{z}/{x}/{y}.png
+//   dir:<path>             a local directory laid out as <path>/{z}/{x}/{y}.png
+//   mbtiles:<path>         a local mbtiles file
+//   none                   no tiles, just a plain background
+// without MAP_TILE_PROVIDER thunderforest is used when there's an api key
+#[derive(Debug, Clone)]
+pub enum TileProvider {
+    Thunderforest { style: String, api_key: String },
+    OpenStreetMap,
+    Xyz { url_template: String },
+    LocalDir { path: PathBuf },
+    MbTiles { path: PathBuf },
+}
+
+impl TileProvider {
+    pub fn from_env() -> Option<Self> {
+        let setting = match env_utils::get_map_tile_provider() {
+            Some(setting) => setting,
+            None => {
+                return Self::thunderforest("cycle").or_else(|| {
+                    tracing::warn!(
+                        "No THUNDERFOREST_API_KEY set, maps will have a plain background"
+                    );
+                    None
+                })
+            }
+        };
+
+        let (kind, value) = match setting.split_once(':') {
+            Some((kind, value)) => (kind, Some(value)),
+            None => (setting.as_str(), None),
+        };
+
+        match (kind.to_lowercase().as_str(), value) {
+            ("thunderforest", style) => Self::thunderforest(style.unwrap_or("cycle")),
+            ("osm" | "openstreetmap", _) => Some(TileProvider::OpenStreetMap),
+            ("xyz", Some(url_template)) => Some(TileProvider::Xyz {
+                url_template: url_template.to_string(),
+            }),
+            ("dir", Some(path)) => Some(TileProvider::LocalDir {
+                path: PathBuf::from(path),
+            }),
+            ("mbtiles", Some(path)) => Some(TileProvider::MbTiles {
+                path: PathBuf::from(path),
+            }),
+            ("none", _) => None,
+            _ => {
+                tracing::error!(
+                    "Unknown MAP_TILE_PROVIDER: {}, maps will have a plain background",
+                    setting
+                );
+                None
+            }
+        }
+    }
+
+    fn thunderforest(style: &str) -> Option<Self> {
+        match env_utils::get_thunderforest_api_key() {
+            Some(api_key) => Some(TileProvider::Thunderforest {
+                style: style.to_string(),
+                api_key,
+            }),
+            None => {
+                tracing::error!("Thunderforest tiles need THUNDERFOREST_API_KEY");
+                None
+            }
+        }
+    }
+
+    // short name for the provider, safe to use in file paths
+    pub fn name(&self) -> String {
+        match self {
+            TileProvider::Thunderforest { style, .. } => format!("thunderforest-{style}"),
+            TileProvider::OpenStreetMap => "osm".to_string(),
+            TileProvider::Xyz { url_template } => {
+                format!(
+                    "xyz-{}",
+                    &shared_lib::utils::hash_string(url_template)[0..12]
+                )
+            }
+            TileProvider::LocalDir { .. } => "dir".to_string(),
+            TileProvider::MbTiles { .. } => "mbtiles".to_string(),
+        }
+    }
+
+    // local providers are already on disk, so there's no point caching them
+    fn is_remote(&self) -> bool {
+        !matches!(
+            self,
+            TileProvider::LocalDir { .. } | TileProvider::MbTiles { .. }
+        )
+    }
+
+    // gets a tile from the cache when there's a fresh copy, otherwise from the provider
+    pub async fn get_tile(&self, z: u8, x: u32, y: u32) -> anyhow::Result<Vec<u8>> {
+        let cache = tile_cache::get_tile_cache().filter(|_| self.is_remote());
+
+        if let Some(cache) = cache {
+            if let Some(tile) = cache.get(self, z, x, y).await {
+                return Ok(tile);
+            }
+        }
+
+        let tile = self.fetch_tile(z, x, y).await?;
+        if let Some(cache) = cache {
+            cache.put(self, z, x, y, &tile).await;
+        }
+
+        Ok(tile)
+    }
+
+    async fn fetch_tile(&self, z: u8, x: u32, y: u32) -> anyhow::Result<Vec<u8>> {
+        let url_template = match self {
+            TileProvider::Thunderforest { style, api_key } => {
+                format!(
+                    "https://tile.thunderforest.com/{style}/{{z}}/{{x}}/{{y}}.png?apikey={api_key}"
+                )
+            }
+            TileProvider::OpenStreetMap => {
+                "https://tile.openstreetmap.org/{z}/{x}/{y}.png".to_string()
+            }
+            TileProvider::Xyz { url_template } => url_template.clone(),
+            TileProvider::LocalDir { path } => {
+                let tile_path = path
+                    .join(z.to_string())
+                    .join(x.to_string())
+                    .join(format!("{y}.png"));
+                return Ok(tokio::fs::read(tile_path).await?);
+            }
+            TileProvider::MbTiles { path } => return read_mbtiles_tile(path, z, x, y).await,
+        };
+
+        let url = url_template
+            .replace("{z}", &z.to_string())
+            .replace("{x}", &x.to_string())
+            .replace("{y}", &y.to_string());
+
+        let resp = HTTP_CLIENT.get(&url).send().await?;
+        if !resp.status().is_success() {
+            return Err(anyhow::anyhow!(
+                "Tile request for {}/{}/{} failed with status {}",
+                z,
+                x,
+                y,
+                resp.status()
+            ));
+        }
+
+        Ok(resp.bytes().await?.to_vec())
+    }
+}
+
+async fn open_mbtiles(path: &Path) -> anyhow::Result<Arc<libsql::Database>> {
+    let mut opened = MBTILES.lock().await;
+    if let Some(db) = opened.get(path) {
+        return Ok(db.clone());
+    }
+
+    tracing::info!("Opening mbtiles file {:?}", path);
+    let db = libsql::Builder::new_local(path)
+        .flags(libsql::OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .build()
+        .await?;
+    let db = Arc::new(db);
+    opened.insert(path.to_path_buf(), db.clone());
+
+    Ok(db)
+}
+
+async fn read_mbtiles_tile(path: &Path, z: u8, x: u32, y: u32) -> anyhow::Result<Vec<u8>> {
+    let db = open_mbtiles(path).await?;
+    let conn = db.connect()?;
+
+    // mbtiles rows are numbered from the bottom (tms), xyz tiles from the top
+    let tile_row = (1_u32 << z) - 1 - y;
+
+    let mut rows = conn
+        .query(
+            "SELECT tile_data FROM tiles WHERE zoom_level = ? AND tile_column = ? AND tile_row = ?",
+            libsql::params!(z, x, tile_row),
+        )
+        .await?;
+
+    match rows.next().await? {
+        Some(row) => Ok(row.get::<Vec<u8>>(0)?),
+        None => Err(anyhow::anyhow!("No tile {}/{}/{} in {:?}", z, x, y, path)),
+    }
+}
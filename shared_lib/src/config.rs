@@ -0,0 +1,55 @@
+use std::sync::LazyLock;
+
+use serde::de::DeserializeOwned;
+
+use crate::env_utils;
+
+// the parsed config file, loaded once on first use
+static CONFIG: LazyLock<serde_json::Value> = LazyLock::new(|| {
+    let path = match env_utils::get_config_path() {
+        Some(path) => path,
+        None => {
+            tracing::debug!("No CONFIG_PATH set, using default config");
+            return serde_json::Value::Null;
+        }
+    };
+
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) => {
+            tracing::error!("Failed to read config file {}: {}", path, e);
+            return serde_json::Value::Null;
+        }
+    };
+
+    match serde_json::from_str(&contents) {
+        Ok(config) => {
+            tracing::info!("Loaded config file {}", path);
+            config
+        }
+        Err(e) => {
+            tracing::error!("Failed to parse config file {}: {}", path, e);
+            serde_json::Value::Null
+        }
+    }
+});
+
+// deserialize a top level section of the config file, falling back to the default when
+// the section is missing or invalid
+pub fn load_section<T>(section: &str) -> T
+where
+    T: DeserializeOwned + Default,
+{
+    let value = match CONFIG.get(section) {
+        Some(value) => value.clone(),
+        None => return T::default(),
+    };
+
+    match serde_json::from_value(value) {
+        Ok(section) => section,
+        Err(e) => {
+            tracing::error!("Invalid `{}` section in config file: {}", section, e);
+            T::default()
+        }
+    }
+}
@@ -1,4 +1,6 @@
 use std::env;
+use std::sync::LazyLock;
+use std::time::SystemTime;
 
 use tracing::error;
 
@@ -16,6 +18,14 @@ pub fn get_host_uri() -> String {
     }
 }
 
+// only set when the app is reachable from outside, for links that other services fetch
+pub fn get_public_host_uri() -> Option<String> {
+    match env::var("HOST").is_ok() || env::var("FLY_APP_NAME").is_ok() {
+        true => Some(get_host_uri()),
+        false => None,
+    }
+}
+
 pub fn get_port() -> u16 {
     let default_port: u16 = 8080;
 
@@ -43,6 +53,21 @@ pub fn get_webhook_secret() -> String {
     hash_string(&wh_seed)[0..32].to_string()
 }
 
+// secret shared with the webhook sender, used to sign trail event requests
+pub fn get_webhook_signing_secret() -> Option<String> {
+    env::var("WH_SIGNING_SECRET").ok()
+}
+
+// token that forwarded emails and sms have to carry, sent as X-Inbound-Token or the `token` query param
+pub fn get_inbound_token() -> Option<String> {
+    env::var("INBOUND_TOKEN").ok()
+}
+
+// token needed to export live beacon tracks, sent as X-Export-Token or the `token` query param
+pub fn get_export_token() -> Option<String> {
+    env::var("EXPORT_TOKEN").ok()
+}
+
 pub fn get_strava_user_id() -> Option<String> {
     env::var("STRAVA_USER_ID").ok()
 }
@@ -57,3 +82,53 @@ pub fn get_db_encryption_key() -> String {
 pub fn get_thunderforest_api_key() -> Option<String> {
     env::var("THUNDERFOREST_API_KEY").ok()
 }
+
+pub fn get_map_tile_provider() -> Option<String> {
+    env::var("MAP_TILE_PROVIDER").ok()
+}
+
+pub fn get_map_route_style() -> Option<String> {
+    env::var("MAP_ROUTE_STYLE").ok()
+}
+
+pub fn get_map_tile_cache_dir() -> Option<String> {
+    env::var("MAP_TILE_CACHE_DIR").ok()
+}
+
+pub fn get_map_tile_cache_ttl_hours() -> Option<u64> {
+    env::var("MAP_TILE_CACHE_TTL_HOURS").ok()?.parse().ok()
+}
+
+pub fn get_map_tile_cache_max_mb() -> Option<u64> {
+    env::var("MAP_TILE_CACHE_MAX_MB").ok()?.parse().ok()
+}
+
+pub fn get_trail_data_url() -> Option<String> {
+    env::var("TRAIL_DATA_URL").ok()
+}
+
+pub fn get_trail_data_source() -> Option<String> {
+    env::var("TRAIL_DATA_SOURCE").ok()
+}
+
+pub fn get_config_path() -> Option<String> {
+    env::var("CONFIG_PATH").ok()
+}
+
+// unique id for this running instance, used to tell instances apart when sharing the db
+pub fn get_instance_id() -> &'static str {
+    static INSTANCE_ID: LazyLock<String> = LazyLock::new(|| {
+        let host = env::var("FLY_MACHINE_ID")
+            .or_else(|_| env::var("HOSTNAME"))
+            .unwrap_or("localhost".to_string());
+        let started_at = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default();
+
+        let suffix = hash_string(&format!("{host}-{}-{started_at}", std::process::id()));
+        format!("{host}-{}", &suffix[0..8])
+    });
+
+    &INSTANCE_ID
+}
@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock};
+
+use geo::{
+    BoundingRect, Contains, Distance, Geometry, GeometryCollection, Haversine, MultiPolygon,
+};
+use serde::Deserialize;
+
+use crate::trail_structs::TrailSystem;
+
+// how far from a trail system's coordinates still counts as being at it, when the config doesn't say otherwise
+pub const DEFAULT_RADIUS_METERS: f64 = 3000.0;
+const METERS_PER_DEGREE: f64 = 111_320.0;
+// points used to draw a radius geofence as a ring
+const CIRCLE_POINTS: usize = 64;
+
+// an entry in the `trail_geofences` section of the config file, keyed by trail id
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct GeofenceConfig {
+    radius: Option<f64>,
+    // a GeoJSON Polygon or MultiPolygon, either as a geometry, a feature or a feature collection
+    polygon: Option<geojson::GeoJson>,
+}
+
+#[derive(Debug, Clone)]
+enum Area {
+    Radius(f64),
+    Polygon(Arc<MultiPolygon>),
+}
+
+static GEOFENCES: LazyLock<HashMap<u64, Area>> = LazyLock::new(|| {
+    let config: HashMap<u64, GeofenceConfig> = crate::config::load_section("trail_geofences");
+    config
+        .into_iter()
+        .filter_map(|(trail_id, config)| {
+            if let Some(polygon) = config.polygon {
+                match to_multi_polygon(&polygon) {
+                    Ok(area) => return Some((trail_id, Area::Polygon(Arc::new(area)))),
+                    Err(e) => {
+                        tracing::error!("Invalid geofence polygon for trail {}: {}", trail_id, e)
+                    }
+                }
+            }
+            config
+                .radius
+                .filter(|radius| *radius > 0.0)
+                .map(|radius| (trail_id, Area::Radius(radius)))
+        })
+        .collect()
+});
+
+fn to_multi_polygon(geojson: &geojson::GeoJson) -> anyhow::Result<MultiPolygon> {
+    let collection = GeometryCollection::<f64>::try_from(geojson)?;
+    let polygons: Vec<_> = collection
+        .into_iter()
+        .flat_map(|geometry| match geometry {
+            Geometry::Polygon(polygon) => vec![polygon],
+            Geometry::MultiPolygon(multi_polygon) => multi_polygon.0,
+            _ => Vec::new(),
+        })
+        .collect();
+
+    if polygons.is_empty() {
+        return Err(anyhow::anyhow!("No polygons in geofence"));
+    }
+    Ok(MultiPolygon::new(polygons))
+}
+
+// the area that counts as being at a trail system, a radius around its coordinates unless
+// the config gives it a polygon or a different radius
+#[derive(Debug, Clone)]
+pub struct Geofence {
+    pub lat: f64,
+    pub lng: f64,
+    area: Area,
+}
+
+impl Geofence {
+    pub fn for_trail(trail: &TrailSystem) -> Self {
+        Geofence {
+            lat: trail.lat,
+            lng: trail.lng,
+            area: GEOFENCES
+                .get(&trail.id)
+                .cloned()
+                .unwrap_or(Area::Radius(DEFAULT_RADIUS_METERS)),
+        }
+    }
+
+    // meters from the trail system's coordinates when the point is inside the geofence
+    // it's 0 inside a polygon, so a drawn boundary wins over a neighbor's radius
+    pub fn distance_to(&self, lat: f64, lng: f64) -> Option<f64> {
+        let point = geo::Point::new(lng, lat);
+        match &self.area {
+            Area::Radius(radius) => {
+                let distance = Haversine.distance(geo::Point::new(self.lng, self.lat), point);
+                (distance <= *radius).then_some(distance)
+            }
+            Area::Polygon(area) => area.contains(&point).then_some(0.0),
+        }
+    }
+
+    // (min_lng, min_lat, max_lng, max_lat)
+    pub fn bounds(&self) -> (f64, f64, f64, f64) {
+        match &self.area {
+            Area::Radius(radius) => {
+                let lat_delta = radius / METERS_PER_DEGREE;
+                let lng_delta =
+                    radius / (METERS_PER_DEGREE * self.lat.to_radians().cos().max(0.01));
+                (
+                    self.lng - lng_delta,
+                    self.lat - lat_delta,
+                    self.lng + lng_delta,
+                    self.lat + lat_delta,
+                )
+            }
+            Area::Polygon(area) => match area.bounding_rect() {
+                Some(rect) => (rect.min().x, rect.min().y, rect.max().x, rect.max().y),
+                None => (self.lng, self.lat, self.lng, self.lat),
+            },
+        }
+    }
+
+    // the boundary as closed rings of (lng, lat), for drawing on maps
+    pub fn outline(&self) -> Vec<Vec<(f64, f64)>> {
+        match &self.area {
+            Area::Radius(radius) => {
+                let lat_delta = radius / METERS_PER_DEGREE;
+                let lng_delta =
+                    radius / (METERS_PER_DEGREE * self.lat.to_radians().cos().max(0.01));
+                let ring = (0..=CIRCLE_POINTS)
+                    .map(|i| {
+                        let angle = i as f64 / CIRCLE_POINTS as f64 * std::f64::consts::TAU;
+                        (
+                            self.lng + lng_delta * angle.cos(),
+                            self.lat + lat_delta * angle.sin(),
+                        )
+                    })
+                    .collect();
+                vec![ring]
+            }
+            Area::Polygon(area) => area
+                .iter()
+                .flat_map(|polygon| std::iter::once(polygon.exterior()).chain(polygon.interiors()))
+                .map(|ring| ring.coords().map(|coord| (coord.x, coord.y)).collect())
+                .collect(),
+        }
+    }
+}
@@ -1,4 +1,7 @@
+pub mod config;
 pub mod env_utils;
+pub mod geofence;
 pub mod strava_structs;
+pub mod track_export;
 pub mod trail_structs;
 pub mod utils;
@@ -73,6 +73,8 @@ pub struct Activity {
     pub max_speed: f64,
     pub elev_high: f64,
     pub elev_low: f64,
+    // ISO 8601 in UTC
+    pub start_date: Option<String>,
     pub start_latlng: Option<Vec<f64>>,
     pub end_latlng: Option<Vec<f64>>,
     #[serde(flatten)]
@@ -111,3 +113,22 @@ pub struct Map {
     pub summary_polyline: String,
     pub resource_state: i64,
 }
+
+// activity streams keyed by type (`key_by_type=true`), any stream strava doesn't have for the activity is empty
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ActivityStreams {
+    pub time: Stream<i64>,
+    pub distance: Stream<f64>,
+    pub altitude: Stream<f64>,
+    pub velocity_smooth: Stream<f64>,
+    pub grade_smooth: Stream<f64>,
+    pub heartrate: Stream<f64>,
+    pub latlng: Stream<Vec<f64>>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Stream<T> {
+    #[serde(default)]
+    pub data: Vec<T>,
+}
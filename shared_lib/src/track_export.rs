@@ -0,0 +1,434 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use geo::{Distance, Haversine, Point};
+
+use crate::strava_structs::ActivityStreams;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackPoint {
+    pub lat: f64,
+    pub lng: f64,
+    // meters
+    pub elevation: Option<f64>,
+    pub time: Option<DateTime<Utc>>,
+}
+
+// a ride's route, for exporting to other apps
+#[derive(Debug, Clone)]
+pub struct Track {
+    pub name: String,
+    pub points: Vec<TrackPoint>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ExportFormat {
+    Gpx,
+    GeoJson,
+    Kml,
+}
+
+impl ExportFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "gpx" => Some(ExportFormat::Gpx),
+            "geojson" | "json" => Some(ExportFormat::GeoJson),
+            "kml" => Some(ExportFormat::Kml),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Gpx => "application/gpx+xml",
+            ExportFormat::GeoJson => "application/geo+json",
+            ExportFormat::Kml => "application/vnd.google-earth.kml+xml",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Gpx => "gpx",
+            ExportFormat::GeoJson => "geojson",
+            ExportFormat::Kml => "kml",
+        }
+    }
+}
+
+impl Track {
+    // times come from the time stream, which is seconds since the start of the activity
+    pub fn from_streams(
+        name: &str,
+        streams: &ActivityStreams,
+        start_time: Option<DateTime<Utc>>,
+    ) -> Option<Self> {
+        let latlng = &streams.latlng.data;
+        if latlng.is_empty() {
+            return None;
+        }
+
+        let altitude = &streams.altitude.data;
+        let time = &streams.time.data;
+
+        let points = latlng
+            .iter()
+            .enumerate()
+            .filter(|(_, latlng)| latlng.len() == 2)
+            .map(|(i, latlng)| TrackPoint {
+                lat: latlng[0],
+                lng: latlng[1],
+                elevation: altitude.get(i).copied(),
+                time: start_time
+                    .zip(time.get(i))
+                    .map(|(start, offset)| start + chrono::Duration::seconds(*offset)),
+            })
+            .collect();
+
+        Some(Track {
+            name: name.to_owned(),
+            points,
+        })
+    }
+
+    // for when there are no streams, just the route without elevation or times
+    pub fn from_polyline(name: &str, polyline: &str) -> anyhow::Result<Self> {
+        let line_string = polyline::decode_polyline(polyline, 5)?;
+
+        Ok(Track {
+            name: name.to_owned(),
+            points: line_string
+                .coords()
+                .map(|coord| TrackPoint {
+                    lat: coord.y,
+                    lng: coord.x,
+                    elevation: None,
+                    time: None,
+                })
+                .collect(),
+        })
+    }
+
+    // drops the points within `radius` meters of where the track starts and ends,
+    // so a public export doesn't lead back to someone's house
+    pub fn trim_ends(mut self, radius: f64) -> Self {
+        let outside = |from: &TrackPoint, point: &TrackPoint| {
+            Haversine.distance(
+                Point::new(from.lng, from.lat),
+                Point::new(point.lng, point.lat),
+            ) > radius
+        };
+
+        let (first, last) = match (self.points.first(), self.points.last()) {
+            (Some(first), Some(last)) => (first.clone(), last.clone()),
+            _ => return self,
+        };
+        let start = self.points.iter().position(|point| outside(&first, point));
+        let end = self.points.iter().rposition(|point| outside(&last, point));
+
+        self.points = match (start, end) {
+            (Some(start), Some(end)) if start <= end => self.points.drain(start..=end).collect(),
+            _ => Vec::new(),
+        };
+        self
+    }
+
+    pub fn export(&self, format: ExportFormat) -> String {
+        match format {
+            ExportFormat::Gpx => self.to_gpx(),
+            ExportFormat::GeoJson => self.to_geojson(),
+            ExportFormat::Kml => self.to_kml(),
+        }
+    }
+
+    // GPX 1.1
+    fn to_gpx(&self) -> String {
+        let mut gpx = String::from(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+            <gpx version=\"1.1\" creator=\"troyonthetrails\" xmlns=\"http://www.topografix.com/GPX/1/1\">\n",
+        );
+        gpx.push_str(&format!(
+            "  <trk>\n    <name>{}</name>\n    <trkseg>\n",
+            escape_xml(&self.name)
+        ));
+
+        for point in &self.points {
+            gpx.push_str(&format!(
+                "      <trkpt lat=\"{}\" lon=\"{}\">",
+                point.lat, point.lng
+            ));
+            if let Some(elevation) = point.elevation {
+                gpx.push_str(&format!("<ele>{elevation}</ele>"));
+            }
+            if let Some(time) = point.time {
+                gpx.push_str(&format!("<time>{}</time>", format_time(time)));
+            }
+            gpx.push_str("</trkpt>\n");
+        }
+
+        gpx.push_str("    </trkseg>\n  </trk>\n</gpx>\n");
+        gpx
+    }
+
+    // a single LineString feature, times go in the coordTimes property like other GeoJSON exporters do
+    fn to_geojson(&self) -> String {
+        let coordinates: Vec<Vec<f64>> = self
+            .points
+            .iter()
+            .map(|point| match point.elevation {
+                Some(elevation) => vec![point.lng, point.lat, elevation],
+                None => vec![point.lng, point.lat],
+            })
+            .collect();
+
+        let mut properties = serde_json::json!({ "name": self.name });
+        if self.points.iter().all(|point| point.time.is_some()) && !self.points.is_empty() {
+            let times: Vec<String> = self
+                .points
+                .iter()
+                .filter_map(|point| point.time.map(format_time))
+                .collect();
+            properties["coordTimes"] = serde_json::json!(times);
+        }
+
+        serde_json::json!({
+            "type": "FeatureCollection",
+            "features": [{
+                "type": "Feature",
+                "properties": properties,
+                "geometry": {
+                    "type": "LineString",
+                    "coordinates": coordinates,
+                },
+            }],
+        })
+        .to_string()
+    }
+
+    // a gx:Track when there are times, a plain LineString otherwise
+    fn to_kml(&self) -> String {
+        let has_times = !self.points.is_empty() && self.points.iter().all(|p| p.time.is_some());
+
+        let mut kml = String::from(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+            <kml xmlns=\"http://www.opengis.net/kml/2.2\" xmlns:gx=\"http://www.google.com/kml/ext/2.2\">\n",
+        );
+        kml.push_str(&format!(
+            "  <Document>\n    <name>{0}</name>\n    <Placemark>\n      <name>{0}</name>\n",
+            escape_xml(&self.name)
+        ));
+
+        if has_times {
+            kml.push_str("      <gx:Track>\n");
+            let altitude_mode = match self.points.iter().all(|p| p.elevation.is_some()) {
+                true => "absolute",
+                false => "clampToGround",
+            };
+            kml.push_str(&format!(
+                "        <altitudeMode>{altitude_mode}</altitudeMode>\n"
+            ));
+            for point in &self.points {
+                if let Some(time) = point.time {
+                    kml.push_str(&format!("        <when>{}</when>\n", format_time(time)));
+                }
+            }
+            // gx:coord always needs an altitude, it's ignored when clamped to the ground
+            for point in &self.points {
+                kml.push_str(&format!(
+                    "        <gx:coord>{} {} {}</gx:coord>\n",
+                    point.lng,
+                    point.lat,
+                    point.elevation.unwrap_or_default()
+                ));
+            }
+            kml.push_str("      </gx:Track>\n");
+        } else {
+            let coordinates: Vec<String> = self
+                .points
+                .iter()
+                .map(|point| match point.elevation {
+                    Some(elevation) => format!("{},{},{}", point.lng, point.lat, elevation),
+                    None => format!("{},{}", point.lng, point.lat),
+                })
+                .collect();
+            kml.push_str(&format!(
+                "      <LineString>\n        <tessellate>1</tessellate>\n        <coordinates>{}</coordinates>\n      </LineString>\n",
+                coordinates.join(" ")
+            ));
+        }
+
+        kml.push_str("    </Placemark>\n  </Document>\n</kml>\n");
+        kml
+    }
+}
+
+fn format_time(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Utc};
+
+    use super::{ExportFormat, Track, TrackPoint};
+    use crate::strava_structs::{ActivityStreams, Stream};
+
+    fn point(lat: f64, lng: f64) -> TrackPoint {
+        TrackPoint {
+            lat,
+            lng,
+            elevation: None,
+            time: None,
+        }
+    }
+
+    #[test]
+    fn trim_ends_drops_points_near_the_start_and_end() {
+        // 0.001 degrees of latitude is about 111 meters
+        let track = Track {
+            name: "Ride".to_string(),
+            points: (0..20).map(|i| point(i as f64 * 0.001, 0.0)).collect(),
+        }
+        .trim_ends(400.0);
+
+        let lats: Vec<f64> = track.points.iter().map(|point| point.lat).collect();
+        assert_eq!(lats.first().copied(), Some(0.004));
+        assert_eq!(lats.last().copied(), Some(0.015));
+    }
+
+    #[test]
+    fn trim_ends_drops_short_tracks_entirely() {
+        let track = Track {
+            name: "Ride".to_string(),
+            points: vec![point(0.0, 0.0), point(0.001, 0.0), point(0.002, 0.0)],
+        }
+        .trim_ends(400.0);
+        assert!(track.points.is_empty());
+    }
+
+    fn timed_track() -> Track {
+        let start = DateTime::<Utc>::from_timestamp(1_700_000_000, 0);
+        Track {
+            name: "Lunch & Laps".to_string(),
+            points: vec![
+                TrackPoint {
+                    lat: 40.5,
+                    lng: -105.1,
+                    elevation: Some(1500.0),
+                    time: start,
+                },
+                TrackPoint {
+                    lat: 40.6,
+                    lng: -105.2,
+                    elevation: Some(1510.5),
+                    time: start.map(|start| start + chrono::Duration::seconds(30)),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn gpx_includes_elevation_and_times() {
+        let gpx = timed_track().export(ExportFormat::Gpx);
+
+        assert!(gpx.contains("<name>Lunch &amp; Laps</name>"));
+        assert!(gpx.contains(
+            "<trkpt lat=\"40.5\" lon=\"-105.1\"><ele>1500</ele><time>2023-11-14T22:13:20Z</time></trkpt>"
+        ));
+        assert!(gpx.contains(
+            "<trkpt lat=\"40.6\" lon=\"-105.2\"><ele>1510.5</ele><time>2023-11-14T22:13:50Z</time></trkpt>"
+        ));
+    }
+
+    #[test]
+    fn gpx_leaves_out_missing_elevation_and_times() {
+        let track = Track {
+            name: "Ride".to_string(),
+            points: vec![point(40.5, -105.1)],
+        };
+        let gpx = track.export(ExportFormat::Gpx);
+
+        assert!(gpx.contains("<trkpt lat=\"40.5\" lon=\"-105.1\"></trkpt>"));
+        assert!(!gpx.contains("<ele>"));
+        assert!(!gpx.contains("<time>"));
+    }
+
+    #[test]
+    fn geojson_has_coordinates_and_times() {
+        let geojson: serde_json::Value =
+            serde_json::from_str(&timed_track().export(ExportFormat::GeoJson)).unwrap();
+        let feature = &geojson["features"][0];
+
+        assert_eq!(feature["properties"]["name"], "Lunch & Laps");
+        assert_eq!(
+            feature["geometry"]["coordinates"],
+            serde_json::json!([[-105.1, 40.5, 1500.0], [-105.2, 40.6, 1510.5]])
+        );
+        assert_eq!(
+            feature["properties"]["coordTimes"],
+            serde_json::json!(["2023-11-14T22:13:20Z", "2023-11-14T22:13:50Z"])
+        );
+    }
+
+    #[test]
+    fn geojson_skips_times_unless_every_point_has_one() {
+        let mut track = timed_track();
+        track.points[1].time = None;
+        let geojson: serde_json::Value =
+            serde_json::from_str(&track.export(ExportFormat::GeoJson)).unwrap();
+
+        assert!(geojson["features"][0]["properties"]
+            .get("coordTimes")
+            .is_none());
+    }
+
+    #[test]
+    fn kml_uses_a_gx_track_when_there_are_times() {
+        let kml = timed_track().export(ExportFormat::Kml);
+
+        assert!(kml.contains("<gx:Track>"));
+        assert!(kml.contains("<altitudeMode>absolute</altitudeMode>"));
+        assert!(kml.contains("<when>2023-11-14T22:13:20Z</when>"));
+        assert!(kml.contains("<gx:coord>-105.2 40.6 1510.5</gx:coord>"));
+        assert!(!kml.contains("<LineString>"));
+    }
+
+    #[test]
+    fn kml_uses_a_line_string_without_times() {
+        let track = Track {
+            name: "Ride".to_string(),
+            points: vec![point(40.5, -105.1), point(40.6, -105.2)],
+        };
+        let kml = track.export(ExportFormat::Kml);
+
+        assert!(kml.contains("<coordinates>-105.1,40.5 -105.2,40.6</coordinates>"));
+        assert!(!kml.contains("<gx:Track>"));
+    }
+
+    #[test]
+    fn from_streams_offsets_times_from_the_start() {
+        let streams = ActivityStreams {
+            time: Stream { data: vec![0, 30] },
+            altitude: Stream {
+                data: vec![1500.0, 1510.5],
+            },
+            latlng: Stream {
+                data: vec![vec![40.5, -105.1], vec![40.6, -105.2]],
+            },
+            ..ActivityStreams::default()
+        };
+        let start = DateTime::<Utc>::from_timestamp(1_700_000_000, 0);
+
+        let track = Track::from_streams("Lunch & Laps", &streams, start).unwrap();
+        assert_eq!(track.points, timed_track().points);
+
+        let untimed = Track::from_streams("Ride", &streams, None).unwrap();
+        assert!(untimed.points.iter().all(|point| point.time.is_none()));
+        assert!(Track::from_streams("Ride", &ActivityStreams::default(), start).is_none());
+    }
+}
@@ -1,3 +1,5 @@
+use std::fmt;
+
 use serde::{Deserialize, Deserializer};
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -9,6 +11,30 @@ pub enum TrailStatus {
     Unknown,
 }
 
+impl From<&str> for TrailStatus {
+    fn from(value: &str) -> Self {
+        match value.to_lowercase().as_str() {
+            "open" => TrailStatus::Open,
+            "caution" => TrailStatus::Caution,
+            "closed" => TrailStatus::Closed,
+            "freeze" => TrailStatus::Freeze,
+            _ => TrailStatus::Unknown,
+        }
+    }
+}
+
+impl fmt::Display for TrailStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrailStatus::Open => write!(f, "Open"),
+            TrailStatus::Caution => write!(f, "Caution"),
+            TrailStatus::Closed => write!(f, "Closed"),
+            TrailStatus::Freeze => write!(f, "Freeze"),
+            TrailStatus::Unknown => write!(f, "Unknown"),
+        }
+    }
+}
+
 // custom deserializer for TrailStatus
 // basically allows for the Unknown variant to be used as a catchall
 impl<'de> Deserialize<'de> for TrailStatus {
@@ -20,16 +46,13 @@ impl<'de> Deserialize<'de> for TrailStatus {
         let value = serde_json::Value::deserialize(deserializer)?;
 
         match value {
-            serde_json::Value::String(s) => match s.to_lowercase().as_str() {
-                "open" => Ok(TrailStatus::Open),
-                "caution" => Ok(TrailStatus::Caution),
-                "closed" => Ok(TrailStatus::Closed),
-                "freeze" => Ok(TrailStatus::Freeze),
-                other => {
-                    tracing::warn!("Unknown trail status: {}", other);
-                    Ok(TrailStatus::Unknown)
+            serde_json::Value::String(s) => {
+                let status = TrailStatus::from(s.as_str());
+                if status == TrailStatus::Unknown {
+                    tracing::warn!("Unknown trail status: {}", s);
                 }
-            },
+                Ok(status)
+            }
             serde_json::Value::Null => {
                 tracing::warn!("Null trail status");
                 Ok(TrailStatus::Unknown)
@@ -74,6 +97,56 @@ pub struct TrailSystem {
     pub latest_status_update_at: Option<String>,
     pub predicted_status: Option<PredictedStatus>,
     pub stats: Option<TrailStatsDisplay>,
+    pub status_stats: Option<TrailStatusStats>,
+}
+
+// how often a trail system is open, worked out from its recorded status history
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq)]
+pub struct TrailStatusStats {
+    // days since the history started, and how many of them the trail was open at some point
+    pub tracked_days: i64,
+    pub open_days: i64,
+    // median seconds from closing until it opens again, None until a closure has ended
+    pub typical_closure: Option<i64>,
+    // unix timestamp of when the trail was last open, None if it never has been
+    pub last_open: Option<i64>,
+    pub open_now: bool,
+}
+
+impl TrailStatusStats {
+    pub fn percent_open(&self) -> String {
+        match self.tracked_days {
+            0 => "0%".to_string(),
+            days => format!("{:.0}%", self.open_days as f64 / days as f64 * 100.0),
+        }
+    }
+
+    pub fn typical_closure_human_readable(&self) -> String {
+        match self.typical_closure {
+            None => "".to_string(),
+            Some(seconds) if seconds < 48 * 3600 => match (seconds as f64 / 3600.0).round() {
+                hours if hours <= 1.0 => "1 hour".to_string(),
+                hours => format!("{hours:.0} hours"),
+            },
+            Some(seconds) => format!("{:.0} days", seconds as f64 / 86400.0),
+        }
+    }
+
+    pub fn last_open_human_readable(&self) -> String {
+        if self.open_now {
+            return "now".to_string();
+        }
+
+        match self
+            .last_open
+            .and_then(|timestamp| chrono::DateTime::<chrono::Utc>::from_timestamp(timestamp, 0))
+        {
+            Some(last_open) => {
+                crate::utils::utc_to_time_ago_human_readable(&last_open.to_rfc3339())
+            }
+            None => "never".to_string(),
+        }
+    }
 }
 
 impl TryFrom<TrailSystem> for geo::Point {
@@ -93,6 +166,8 @@ pub struct TrailStats {
     pub rides: i32,
     pub achievement_count: i64,
     pub total_moving_time: i64,
+    // the most recent ride at the trail
+    pub last_ride_id: Option<i64>,
 }
 
 impl PartialEq for TrailStats {
@@ -107,6 +182,9 @@ pub struct TrailStatsDisplay {
     pub rides: String,
     pub achievement_count: i64,
     pub total_moving_time: String,
+    // an svg drawing of the last ride at the trail
+    #[serde(default)]
+    pub route_svg: Option<String>,
 }
 
 impl From<TrailStats> for TrailStatsDisplay {
@@ -133,6 +211,7 @@ impl From<TrailStats> for TrailStatsDisplay {
             rides,
             achievement_count: stats.achievement_count,
             total_moving_time,
+            route_svg: None,
         }
     }
 }
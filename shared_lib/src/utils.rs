@@ -1,5 +1,6 @@
 use chrono::{DateTime, Utc};
 use geo::{Distance, Haversine};
+use hmac::{Hmac, Mac};
 use serde_json::{self};
 use sha2::{Digest, Sha256};
 use std::collections::HashMap;
@@ -112,6 +113,37 @@ pub fn hash_string(string: &str) -> String {
     format!("{:x}", hasher.finalize())
 }
 
+// checks a hex encoded HMAC-SHA256 signature in constant time
+pub fn verify_hmac_sha256(secret: &str, message: &[u8], signature_hex: &str) -> bool {
+    let signature = match decode_hex(signature_hex) {
+        Some(signature) => signature,
+        None => return false,
+    };
+
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC can take a key of any size");
+    mac.update(message);
+    mac.verify_slice(&signature).is_ok()
+}
+
+// compares a secret token against the expected one, comparing digests keeps the time taken
+// independent of how much of the token matches
+pub fn tokens_match(token: &str, expected: &str) -> bool {
+    hash_string(token) == hash_string(expected)
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    // from_str_radix alone would also accept a leading '+'
+    if !hex.len().is_multiple_of(2) || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
 pub fn struct_to_hashmap<T>(s: T) -> HashMap<String, serde_json::Value>
 where
     T: serde::Serialize,
@@ -138,3 +170,46 @@ pub fn haversine_distance(
 
     Ok(Haversine.distance(a_point, b_point))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_hex, verify_hmac_sha256};
+
+    // RFC 4231 test case 2
+    const SECRET: &str = "Jefe";
+    const MESSAGE: &[u8] = b"what do ya want for nothing?";
+    const SIGNATURE: &str = "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843";
+
+    #[test]
+    fn decode_hex_decodes_both_cases() {
+        assert_eq!(decode_hex("00ff7fAb"), Some(vec![0x00, 0xff, 0x7f, 0xab]));
+        assert_eq!(decode_hex(""), Some(vec![]));
+    }
+
+    #[test]
+    fn decode_hex_rejects_invalid_input() {
+        assert_eq!(decode_hex("abc"), None);
+        assert_eq!(decode_hex("zz"), None);
+        assert_eq!(decode_hex("+1"), None);
+        assert_eq!(decode_hex("é1"), None);
+    }
+
+    #[test]
+    fn verify_hmac_sha256_accepts_valid_signature() {
+        assert!(verify_hmac_sha256(SECRET, MESSAGE, SIGNATURE));
+        assert!(verify_hmac_sha256(
+            SECRET,
+            MESSAGE,
+            &SIGNATURE.to_uppercase()
+        ));
+    }
+
+    #[test]
+    fn verify_hmac_sha256_rejects_invalid_signature() {
+        assert!(!verify_hmac_sha256("not the secret", MESSAGE, SIGNATURE));
+        assert!(!verify_hmac_sha256(SECRET, b"another message", SIGNATURE));
+        assert!(!verify_hmac_sha256(SECRET, MESSAGE, &SIGNATURE[..62]));
+        assert!(!verify_hmac_sha256(SECRET, MESSAGE, "not hex"));
+        assert!(!verify_hmac_sha256(SECRET, MESSAGE, ""));
+    }
+}
@@ -1,6 +1,7 @@
 use core::fmt;
 
 use chrono::{DateTime, TimeZone, Utc};
+use db_service::beacon_tracks::BeaconPoint;
 use serde::{
     de::{self, Visitor},
     Deserialize, Deserializer, Serialize, Serializer,
@@ -21,6 +22,36 @@ pub struct BeaconData {
     pub activity_id: Option<i64>,
 }
 
+impl BeaconData {
+    // most recent (lat, lng) reported by the beacon, if any
+    pub fn last_latlng(&self) -> Option<(f64, f64)> {
+        self.streams
+            .latlng
+            .iter()
+            .rev()
+            .find(|latlng| latlng.len() == 2)
+            .map(|latlng| (latlng[0], latlng[1]))
+    }
+
+    // the breadcrumbs so far, timestamps in the stream can be in milliseconds so they're normalized to seconds
+    pub fn track_points(&self) -> Vec<BeaconPoint> {
+        self.streams
+            .timestamp
+            .iter()
+            .zip(&self.streams.latlng)
+            .filter(|(_, latlng)| latlng.len() == 2)
+            .map(|(timestamp, latlng)| BeaconPoint {
+                recorded_at: match *timestamp > 1_000_000_000_000 {
+                    true => timestamp / 1000,
+                    false => *timestamp,
+                },
+                lat: latlng[0],
+                lng: latlng[1],
+            })
+            .collect()
+    }
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Streams {
     #[serde(default)]
@@ -137,6 +168,36 @@ impl<'de> Deserialize<'de> for EpochDateTime {
     }
 }
 
+// whether the url points at a strava beacon, we only ever want to poll strava with these
+pub fn is_beacon_url(beacon_url: &str) -> bool {
+    let url = match url::Url::parse(beacon_url) {
+        Ok(url) => url,
+        Err(_) => return false,
+    };
+
+    let is_strava_host = match url.host_str() {
+        Some(host) => host == "strava.com" || host.ends_with(".strava.com"),
+        None => false,
+    };
+
+    url.scheme() == "https" && is_strava_host && url.path().starts_with("/beacon/")
+}
+
+// pulls the first strava beacon url out of free text, e.g. the body of a beacon sms or email
+pub fn find_beacon_url(text: &str) -> Option<String> {
+    text.match_indices("https://")
+        .map(|(start, _)| {
+            let candidate = &text[start..];
+            let end = candidate
+                .find(|c: char| c.is_whitespace() || matches!(c, '"' | '\'' | '<' | '>'))
+                .unwrap_or(candidate.len());
+            candidate[..end]
+                .trim_end_matches(['.', ',', ';', ':', '!', '?', ')', ']'])
+                .replace("&amp;", "&")
+        })
+        .find(|candidate| is_beacon_url(candidate))
+}
+
 pub async fn get_beacon_data(beacon_url: String) -> anyhow::Result<BeaconData> {
     let client = reqwest::Client::new();
     let resp = client
@@ -156,3 +217,25 @@ pub async fn get_beacon_data(beacon_url: String) -> anyhow::Result<BeaconData> {
         ))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::is_beacon_url;
+
+    #[test]
+    fn accepts_strava_beacon_urls() {
+        assert!(is_beacon_url("https://www.strava.com/beacon/abc123"));
+        assert!(is_beacon_url("https://strava.com/beacon/abc123?x=1"));
+    }
+
+    #[test]
+    fn rejects_other_urls() {
+        assert!(!is_beacon_url("http://www.strava.com/beacon/abc123"));
+        assert!(!is_beacon_url("https://www.strava.com/activities/123"));
+        assert!(!is_beacon_url("https://evilstrava.com/beacon/abc123"));
+        assert!(!is_beacon_url("https://strava.com.evil.com/beacon/abc123"));
+        assert!(!is_beacon_url("https://evil.com/beacon/abc123?strava.com"));
+        assert!(!is_beacon_url("https://user@evil.com/beacon/abc123"));
+        assert!(!is_beacon_url("not a url"));
+    }
+}
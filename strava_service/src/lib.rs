@@ -1,6 +1,7 @@
 pub mod auth;
 pub mod beacon;
 
+use std::collections::VecDeque;
 use std::time::Duration;
 
 use anyhow::Context;
@@ -13,7 +14,7 @@ use tokio::sync::Mutex;
 use tokio::time::{sleep, Instant};
 
 use shared_lib::env_utils;
-use shared_lib::strava_structs::{Activity, StravaData};
+use shared_lib::strava_structs::{Activity, ActivityStreams, StravaData};
 
 pub struct AthelteStatsCache {
     pub stats: StravaData,
@@ -173,6 +174,60 @@ pub async fn get_activity(activity_id: i64) -> anyhow::Result<Activity> {
     }
 }
 
+// streams don't change once a ride is uploaded, so the most recently used ones are kept around
+const MAX_CACHED_STREAMS: usize = 20;
+
+static CACHE_STREAMS: LazyLock<Mutex<VecDeque<(i64, ActivityStreams)>>> =
+    LazyLock::new(|| Mutex::new(VecDeque::new()));
+
+pub async fn get_activity_streams(activity_id: i64) -> anyhow::Result<ActivityStreams> {
+    {
+        let mut guard = CACHE_STREAMS.lock().await;
+        if let Some(index) = guard.iter().position(|(id, _)| *id == activity_id) {
+            tracing::trace!("Using cached activity streams for {}", activity_id);
+            let entry = guard.remove(index).unwrap();
+            let streams = entry.1.clone();
+            guard.push_back(entry);
+            return Ok(streams);
+        }
+    }
+
+    let streams = fetch_activity_streams(activity_id).await?;
+
+    {
+        let mut guard = CACHE_STREAMS.lock().await;
+        guard.retain(|(id, _)| *id != activity_id);
+        guard.push_back((activity_id, streams.clone()));
+        while guard.len() > MAX_CACHED_STREAMS {
+            guard.pop_front();
+        }
+    }
+
+    Ok(streams)
+}
+
+async fn fetch_activity_streams(activity_id: i64) -> anyhow::Result<ActivityStreams> {
+    let resp = get_strava_data(format!(
+        "https://www.strava.com/api/v3/activities/{activity_id}/streams?keys=time,distance,altitude,velocity_smooth,grade_smooth,heartrate,latlng&key_by_type=true"
+    ))
+    .await?;
+
+    if resp.status().is_success() {
+        let text = resp.text().await.context("Failed to get strava data")?;
+
+        let streams: ActivityStreams =
+            serde_json::from_str(&text).context("Failed to deserialize JSON")?;
+
+        Ok(streams)
+    } else {
+        Err(anyhow::anyhow!(
+            "Received a non-success status code {}: {}",
+            resp.status(),
+            resp.text().await.unwrap_or("Unknown error".to_string())
+        ))
+    }
+}
+
 static CACHE_RIDES: LazyLock<Arc<Mutex<Option<RidesCache>>>> =
     LazyLock::new(|| Arc::new(Mutex::new(None)));
 
@@ -191,6 +246,19 @@ pub async fn get_cached_activities(ttl: Option<u64>) -> Option<Vec<Activity>> {
     None
 }
 
+// one of the athlete's rides from the cached activity list, None for any other id
+pub async fn get_known_activity(activity_id: i64) -> anyhow::Result<Option<Activity>> {
+    Ok(get_all_activities()
+        .await?
+        .into_iter()
+        .find(|activity| activity.id == activity_id))
+}
+
+// whether the activity is one of the athlete's rides, so public endpoints don't fetch arbitrary ids from strava
+pub async fn is_known_activity(activity_id: i64) -> anyhow::Result<bool> {
+    Ok(get_known_activity(activity_id).await?.is_some())
+}
+
 pub async fn get_all_activities() -> anyhow::Result<Vec<Activity>> {
     if let Some(cached_rides) = get_cached_activities(Some(60 * 5)).await {
         tracing::trace!("Using cached rides");
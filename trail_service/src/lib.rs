@@ -1,2 +1,6 @@
 pub mod ride_counts;
+pub mod ride_matching;
+pub mod status_history;
 pub mod trail_data;
+pub mod trail_lookup;
+pub mod trail_source;
@@ -3,28 +3,29 @@ use std::collections::HashMap;
 use shared_lib::{
     strava_structs::Activity,
     trail_structs::{TrailStats, TrailSystem},
-    utils,
 };
 
+use crate::ride_matching;
+
+// a ride counts toward every trail system it spent time at, with the moving time split between them
 pub fn calculate_stats(trails: Vec<TrailSystem>, rides: Vec<Activity>) -> HashMap<u64, TrailStats> {
     let counts = rides.iter().fold(HashMap::new(), |mut counts, ride| {
-        let closest_trail = trails
-            .iter()
-            .filter_map(|trail| {
-                let distance = utils::haversine_distance(ride.clone(), trail.clone()).ok()?;
-                (distance <= 3000.0).then_some((trail.id, distance))
-            })
-            .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+        let visits = ride_matching::match_ride(&trails, ride);
 
-        if let Some((id, _)) = closest_trail {
-            let entry = counts.entry(id).or_insert(TrailStats {
-                id,
+        for (i, visit) in visits.iter().enumerate() {
+            let entry = counts.entry(visit.trail_id).or_insert(TrailStats {
+                id: visit.trail_id,
                 ..Default::default()
             });
 
             entry.rides += 1;
-            entry.achievement_count += ride.achievement_count;
-            entry.total_moving_time += ride.moving_time;
+            // achievements can't be split by trail, they go to where most of the ride was
+            if i == 0 {
+                entry.achievement_count += ride.achievement_count;
+            }
+            entry.total_moving_time += visit.moving_time;
+            // rides come newest first
+            entry.last_ride_id.get_or_insert(ride.id);
         }
 
         counts
@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+
+use geo::{Distance, Haversine, Point};
+use shared_lib::{geofence::Geofence, strava_structs::Activity, trail_structs::TrailSystem};
+
+use crate::trail_lookup;
+
+// a ride has to cover this much ground near a trail system to count as riding there,
+// so driving past or a short warm up loop near another trail doesn't count
+const MIN_TRAIL_DISTANCE_METERS: f64 = 500.0;
+
+// the part of a ride spent at one trail system
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrailVisit {
+    pub trail_id: u64,
+    pub distance: f64,
+    // seconds, the ride's moving time split by how much of the route was near the trail
+    pub moving_time: i64,
+}
+
+// every trail system the ride spent time at, the trail with the most distance first
+// each stretch of the route goes to the closest trail system whose geofence it's in
+// rides without a route fall back to the trail closest to where they started
+pub fn match_ride(trails: &[TrailSystem], ride: &Activity) -> Vec<TrailVisit> {
+    let route = ride
+        .map
+        .as_ref()
+        .filter(|map| !map.summary_polyline.is_empty())
+        .and_then(|map| polyline::decode_polyline(&map.summary_polyline, 5).ok())
+        .filter(|line_string| line_string.0.len() >= 2);
+
+    let route = match route {
+        Some(route) => route,
+        None => return match_start(trails, ride).into_iter().collect(),
+    };
+
+    let points: Vec<Point> = route.points().collect();
+    let trails = trails_near(trails, &points);
+    if trails.is_empty() {
+        return Vec::new();
+    }
+
+    let mut distances: HashMap<u64, f64> = HashMap::new();
+    let mut total_distance = 0.0;
+    for segment in points.windows(2) {
+        let length = Haversine.distance(segment[0], segment[1]);
+        total_distance += length;
+
+        let midpoint = Point::new(
+            (segment[0].x() + segment[1].x()) / 2.0,
+            (segment[0].y() + segment[1].y()) / 2.0,
+        );
+        if let Some(trail) = trail_lookup::find_trail_at(&trails, midpoint.y(), midpoint.x()) {
+            *distances.entry(trail.id).or_default() += length;
+        }
+    }
+
+    if total_distance <= 0.0 {
+        return match_start(&trails, ride).into_iter().collect();
+    }
+
+    let mut visits: Vec<TrailVisit> = distances
+        .into_iter()
+        .filter(|(_, distance)| *distance >= MIN_TRAIL_DISTANCE_METERS)
+        .map(|(trail_id, distance)| TrailVisit {
+            trail_id,
+            distance,
+            moving_time: (ride.moving_time as f64 * distance / total_distance).round() as i64,
+        })
+        .collect();
+    visits.sort_by(|a, b| b.distance.total_cmp(&a.distance));
+
+    visits
+}
+
+fn match_start(trails: &[TrailSystem], ride: &Activity) -> Option<TrailVisit> {
+    let start: Point = ride.clone().try_into().ok()?;
+
+    trail_lookup::find_trail_at(trails, start.y(), start.x()).map(|trail| TrailVisit {
+        trail_id: trail.id,
+        distance: ride.distance,
+        moving_time: ride.moving_time,
+    })
+}
+
+// only the trails whose geofence overlaps the route's bounding box, so long rides
+// don't check every point against every trail
+fn trails_near(trails: &[TrailSystem], points: &[Point]) -> Vec<TrailSystem> {
+    let min_lat = points.iter().map(|p| p.y()).fold(f64::MAX, f64::min);
+    let max_lat = points.iter().map(|p| p.y()).fold(f64::MIN, f64::max);
+    let min_lng = points.iter().map(|p| p.x()).fold(f64::MAX, f64::min);
+    let max_lng = points.iter().map(|p| p.x()).fold(f64::MIN, f64::max);
+
+    trails
+        .iter()
+        .filter(|trail| {
+            let (fence_min_lng, fence_min_lat, fence_max_lng, fence_max_lat) =
+                Geofence::for_trail(trail).bounds();
+            fence_min_lat <= max_lat
+                && fence_max_lat >= min_lat
+                && fence_min_lng <= max_lng
+                && fence_max_lng >= min_lng
+        })
+        .cloned()
+        .collect()
+}
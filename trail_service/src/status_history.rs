@@ -0,0 +1,249 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, LazyLock};
+use std::time::SystemTime;
+
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+use db_service::trail_status::TrailStatusChange;
+use shared_lib::trail_structs::{TrailStatus, TrailStatusStats};
+
+const SECONDS_PER_DAY: i64 = 86_400;
+// same as the trail data cache, the history and stats don't need to be any fresher than the statuses they're shown with
+const HISTORY_CACHE_SECS: u64 = 300;
+
+// every trail's history along with the stats worked out from it, both keyed by trail id
+struct HistoryCache {
+    history: HashMap<u64, Vec<TrailStatusChange>>,
+    stats: HashMap<u64, TrailStatusStats>,
+    updated: Instant,
+}
+
+static HISTORY_CACHE: LazyLock<Arc<Mutex<Option<HistoryCache>>>> =
+    LazyLock::new(|| Arc::new(Mutex::new(None)));
+
+// open but wet still counts as open
+fn is_open(status: &TrailStatus) -> bool {
+    matches!(status, TrailStatus::Open | TrailStatus::Caution)
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+// the whole history is read to work out the stats, so it's cached along with them and
+// refreshed at most every HISTORY_CACHE_SECS
+async fn with_cache<T>(read: impl FnOnce(&HistoryCache) -> T) -> T {
+    let mut guard = HISTORY_CACHE.lock().await;
+    if let Some(cache) = &*guard {
+        if cache.updated.elapsed().as_secs() < HISTORY_CACHE_SECS {
+            tracing::trace!("Using cached trail status history");
+            return read(cache);
+        }
+    }
+
+    let cache = load_history().await;
+    let result = read(&cache);
+    *guard = Some(cache);
+    result
+}
+
+async fn load_history() -> HistoryCache {
+    let mut history: HashMap<u64, Vec<TrailStatusChange>> = HashMap::new();
+    for change in db_service::trail_status::get_history(None).await {
+        history.entry(change.trail_id).or_default().push(change);
+    }
+
+    let now = now();
+    let stats = history
+        .iter()
+        .filter_map(|(trail_id, history)| Some((*trail_id, calculate_stats(history, now)?)))
+        .collect();
+
+    HistoryCache {
+        history,
+        stats,
+        updated: Instant::now(),
+    }
+}
+
+// stats for every trail system with recorded history, keyed by trail id
+pub async fn get_all_stats() -> HashMap<u64, TrailStatusStats> {
+    with_cache(|cache| cache.stats.clone()).await
+}
+
+pub async fn get_stats(trail_id: u64) -> Option<TrailStatusStats> {
+    with_cache(|cache| cache.stats.get(&trail_id).copied()).await
+}
+
+// the trail's status changes, oldest first
+pub async fn get_history(trail_id: u64) -> Vec<TrailStatusChange> {
+    with_cache(|cache| cache.history.get(&trail_id).cloned().unwrap_or_default()).await
+}
+
+// history has to be oldest first, each status lasts until the next change (or now for the last one)
+// days are UTC days
+pub fn calculate_stats(history: &[TrailStatusChange], now: i64) -> Option<TrailStatusStats> {
+    let first = history.first()?;
+
+    let mut open_days = HashSet::new();
+    let mut last_open = None;
+    // closures are almost always from rain, so how long they last is how long it takes to dry out
+    let mut closures = Vec::new();
+    let mut closed_since = None;
+
+    for (i, change) in history.iter().enumerate() {
+        let end = history
+            .get(i + 1)
+            .map(|next| next.changed_at)
+            .unwrap_or(now)
+            .max(change.changed_at);
+
+        if is_open(&change.status) {
+            let last_day = (end - 1).max(change.changed_at) / SECONDS_PER_DAY;
+            open_days.extend(change.changed_at / SECONDS_PER_DAY..=last_day);
+            last_open = Some(end);
+        }
+
+        // a closure lasts until the trail opens again, going from closed to frozen or unknown doesn't end it
+        match (&change.status, closed_since) {
+            (TrailStatus::Closed, None) => closed_since = Some(change.changed_at),
+            (status, Some(start)) if is_open(status) => {
+                closures.push(change.changed_at - start);
+                closed_since = None;
+            }
+            _ => {}
+        }
+    }
+
+    closures.sort_unstable();
+    let typical_closure = closures.get(closures.len() / 2).copied();
+
+    Some(TrailStatusStats {
+        tracked_days: now / SECONDS_PER_DAY - first.changed_at / SECONDS_PER_DAY + 1,
+        open_days: open_days.len() as i64,
+        typical_closure,
+        last_open,
+        open_now: history.last().is_some_and(|change| is_open(&change.status)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use db_service::trail_status::TrailStatusChange;
+    use shared_lib::trail_structs::TrailStatus;
+
+    use super::{calculate_stats, SECONDS_PER_DAY};
+
+    const HOUR: i64 = 3600;
+    // midnight utc
+    const DAY_START: i64 = 1_700_006_400;
+
+    fn change(status: TrailStatus, changed_at: i64) -> TrailStatusChange {
+        TrailStatusChange {
+            trail_id: 1,
+            status,
+            previous_status: None,
+            status_description: None,
+            changed_at,
+        }
+    }
+
+    #[test]
+    fn no_history_has_no_stats() {
+        assert!(calculate_stats(&[], DAY_START).is_none());
+    }
+
+    #[test]
+    fn closure_lasts_until_the_trail_opens() {
+        let history = [
+            change(TrailStatus::Open, DAY_START),
+            change(TrailStatus::Closed, DAY_START + HOUR),
+            change(TrailStatus::Open, DAY_START + 5 * HOUR),
+        ];
+        let stats = calculate_stats(&history, DAY_START + 6 * HOUR).unwrap();
+        assert_eq!(stats.typical_closure, Some(4 * HOUR));
+        assert!(stats.open_now);
+    }
+
+    #[test]
+    fn freezing_doesnt_end_a_closure() {
+        let history = [
+            change(TrailStatus::Closed, DAY_START),
+            change(TrailStatus::Freeze, DAY_START + 2 * HOUR),
+            change(TrailStatus::Unknown, DAY_START + 3 * HOUR),
+            change(TrailStatus::Caution, DAY_START + 10 * HOUR),
+        ];
+        let stats = calculate_stats(&history, DAY_START + 11 * HOUR).unwrap();
+        assert_eq!(stats.typical_closure, Some(10 * HOUR));
+    }
+
+    #[test]
+    fn ongoing_closure_isnt_counted() {
+        let history = [
+            change(TrailStatus::Open, DAY_START),
+            change(TrailStatus::Closed, DAY_START + HOUR),
+        ];
+        let stats = calculate_stats(&history, DAY_START + 3 * SECONDS_PER_DAY).unwrap();
+        assert_eq!(stats.typical_closure, None);
+        assert!(!stats.open_now);
+        assert_eq!(stats.last_open, Some(DAY_START + HOUR));
+    }
+
+    #[test]
+    fn freeze_without_a_closure_isnt_a_closure() {
+        let history = [
+            change(TrailStatus::Freeze, DAY_START),
+            change(TrailStatus::Open, DAY_START + HOUR),
+        ];
+        let stats = calculate_stats(&history, DAY_START + 2 * HOUR).unwrap();
+        assert_eq!(stats.typical_closure, None);
+    }
+
+    #[test]
+    fn typical_closure_is_the_median() {
+        let history = [
+            change(TrailStatus::Closed, DAY_START),
+            change(TrailStatus::Open, DAY_START + HOUR),
+            change(TrailStatus::Closed, DAY_START + 2 * HOUR),
+            change(TrailStatus::Open, DAY_START + 5 * HOUR),
+            change(TrailStatus::Closed, DAY_START + 6 * HOUR),
+            change(TrailStatus::Open, DAY_START + 16 * HOUR),
+        ];
+        let stats = calculate_stats(&history, DAY_START + 17 * HOUR).unwrap();
+        assert_eq!(stats.typical_closure, Some(3 * HOUR));
+    }
+
+    #[test]
+    fn open_days_count_each_utc_day_open_at_some_point() {
+        // open from noon on the first day until 2am on the third, then closed for two days
+        let history = [
+            change(TrailStatus::Open, DAY_START + 12 * HOUR),
+            change(
+                TrailStatus::Closed,
+                DAY_START + 2 * SECONDS_PER_DAY + 2 * HOUR,
+            ),
+        ];
+        let stats = calculate_stats(&history, DAY_START + 4 * SECONDS_PER_DAY + HOUR).unwrap();
+        assert_eq!(stats.tracked_days, 5);
+        assert_eq!(stats.open_days, 3);
+        assert_eq!(
+            stats.last_open,
+            Some(DAY_START + 2 * SECONDS_PER_DAY + 2 * HOUR)
+        );
+    }
+
+    #[test]
+    fn open_ending_at_midnight_doesnt_count_the_next_day() {
+        let history = [
+            change(TrailStatus::Open, DAY_START),
+            change(TrailStatus::Closed, DAY_START + SECONDS_PER_DAY),
+        ];
+        let stats = calculate_stats(&history, DAY_START + SECONDS_PER_DAY + HOUR).unwrap();
+        assert_eq!(stats.open_days, 1);
+        assert_eq!(stats.tracked_days, 2);
+    }
+}
@@ -1,14 +1,17 @@
-use anyhow::Context;
 use shared_lib::trail_structs::TrailSystem;
 use std::sync::Arc;
 use std::sync::LazyLock;
 use tokio::sync::Mutex;
 use tokio::time::Instant;
 
+use crate::trail_source::{ConfiguredSource, TrailDataError, TrailDataSource};
+
 #[derive(Default, Clone)]
 pub struct TrailDataCache {
     pub trail_data: Vec<TrailSystem>,
     pub last_updated: Option<Instant>,
+    // why the last fetch failed, the trail data is from the last fetch that worked
+    pub error: Option<String>,
 }
 
 static TRAIL_CACHE: LazyLock<Arc<Mutex<Option<TrailDataCache>>>> =
@@ -26,69 +29,43 @@ pub async fn get_data() -> TrailDataCache {
             return data.clone();
         }
         tracing::trace!("Trail data is stale, fetching new data");
-        if let new_data @ Some(_) =
-            Some(fetch_trail_data().await.unwrap_or_default()).filter(|d| !d.is_empty())
-        {
-            let updated_data = TrailDataCache {
-                trail_data: new_data.unwrap(),
-                last_updated: Some(Instant::now()),
-            };
-            *guard = Some(updated_data.clone());
-            return updated_data;
-        }
-        // if new_data is empty, fall through to default below
+    } else {
+        tracing::trace!("Fetching trail data for the first time");
     }
 
-    tracing::trace!("Fetching trail data for the first time or after empty fetch");
-    let default = TrailDataCache {
-        trail_data: fetch_trail_data().await.unwrap_or_default(),
-        last_updated: Some(Instant::now()),
+    let previous = guard.take().unwrap_or_default();
+    let updated_data = match fetch_trail_data().await {
+        Ok(trail_data) => TrailDataCache {
+            trail_data,
+            last_updated: Some(Instant::now()),
+            error: None,
+        },
+        // keep what we had rather than showing no trails, and try again after the usual wait
+        Err(e) => {
+            tracing::error!("Failed to get trail data: {}", e);
+            TrailDataCache {
+                trail_data: previous.trail_data,
+                last_updated: Some(Instant::now()),
+                error: Some(e.to_string()),
+            }
+        }
     };
-    *guard = Some(default.clone());
-    default
-}
 
-async fn fetch_trail_data() -> anyhow::Result<Vec<TrailSystem>> {
-    let html = get_trail_html().await?;
-    let trail_systems = extract_trail_data(html)?;
-    let sorted_trail_systems = sort_trail_data(trail_systems);
-    Ok(sorted_trail_systems)
+    *guard = Some(updated_data.clone());
+    updated_data
 }
 
-async fn get_trail_html() -> anyhow::Result<String> {
-    let url =
-        std::env::var("TRAIL_DATA_URL").context("TRAIL_DATA_URL environment variable not found")?;
+async fn fetch_trail_data() -> Result<Vec<TrailSystem>, TrailDataError> {
+    let source = ConfiguredSource::from_env()?;
+    let trail_systems = source.fetch().await?;
 
-    let resp = reqwest::get(url)
-        .await
-        .context("Failed to get HTML from data source")?;
-    let html = resp.text().await.context("Couldn't find html body")?;
+    tracing::trace!(
+        "Fetched {} trail systems from the {}",
+        trail_systems.len(),
+        source.name()
+    );
 
-    tracing::trace!("Fetched trail data from data source");
-
-    Ok(html)
-}
-
-fn extract_trail_data(html: String) -> anyhow::Result<Vec<TrailSystem>> {
-    let start_tag = "var trail_systems = ";
-    let end_tag = ";</script>";
-
-    let start = html
-        .find(start_tag)
-        .ok_or(anyhow::anyhow!("Start tag not found"))?
-        + start_tag.len();
-    let end = html[start..]
-        .find(end_tag)
-        .ok_or(anyhow::anyhow!("End tag not found"))?
-        + start;
-
-    let json = &html[start..end];
-
-    let trail_systems = serde_json::from_str(json);
-    match trail_systems {
-        Ok(trail_systems) => Ok(trail_systems),
-        Err(err) => Err(err.into()),
-    }
+    Ok(sort_trail_data(trail_systems))
 }
 
 fn sort_trail_data(trail_data: Vec<TrailSystem>) -> Vec<TrailSystem> {
@@ -0,0 +1,13 @@
+use shared_lib::{geofence::Geofence, trail_structs::TrailSystem};
+
+// finds the closest trail system whose geofence contains the given coordinates, if any
+pub fn find_trail_at(trails: &[TrailSystem], lat: f64, lng: f64) -> Option<&TrailSystem> {
+    trails
+        .iter()
+        .filter_map(|trail| {
+            let distance = Geofence::for_trail(trail).distance_to(lat, lng)?;
+            Some((trail, distance))
+        })
+        .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal))
+        .map(|(trail, _)| trail)
+}
@@ -0,0 +1,356 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::future::Future;
+use std::path::PathBuf;
+
+use shared_lib::env_utils;
+use shared_lib::trail_structs::TrailSystem;
+
+// why trail data couldn't be loaded
+#[derive(Debug)]
+pub enum TrailDataError {
+    // no url or path to load from
+    NotConfigured(String),
+    Request(reqwest::Error),
+    Status(reqwest::StatusCode),
+    Io(std::io::Error),
+    // the scraped page doesn't have the markers around the trail data anymore
+    MarkerNotFound(&'static str),
+    Parse(serde_json::Error),
+    // parsed, but nothing in it looks like a trail system
+    Invalid(String),
+}
+
+impl fmt::Display for TrailDataError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrailDataError::NotConfigured(what) => write!(f, "{what} is not set"),
+            TrailDataError::Request(e) => write!(f, "request failed: {e}"),
+            TrailDataError::Status(status) => write!(f, "received status {status}"),
+            TrailDataError::Io(e) => write!(f, "couldn't read file: {e}"),
+            TrailDataError::MarkerNotFound(marker) => {
+                write!(f, "couldn't find `{marker}` in the page")
+            }
+            TrailDataError::Parse(e) => write!(f, "invalid JSON: {e}"),
+            TrailDataError::Invalid(reason) => write!(f, "invalid trail data: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for TrailDataError {}
+
+// somewhere trail systems can be loaded from
+pub trait TrailDataSource {
+    fn name(&self) -> &'static str;
+
+    fn fetch(&self) -> impl Future<Output = Result<Vec<TrailSystem>, TrailDataError>> + Send;
+}
+
+// the trail status website, the data is embedded in a script tag on the page
+#[derive(Debug, Clone)]
+pub struct HtmlScraper {
+    pub url: String,
+}
+
+impl HtmlScraper {
+    const START_MARKER: &'static str = "var trail_systems = ";
+    const END_MARKER: &'static str = ";</script>";
+}
+
+impl TrailDataSource for HtmlScraper {
+    fn name(&self) -> &'static str {
+        "scraper"
+    }
+
+    async fn fetch(&self) -> Result<Vec<TrailSystem>, TrailDataError> {
+        let html = get_text(&self.url).await?;
+
+        let start = html
+            .find(Self::START_MARKER)
+            .ok_or(TrailDataError::MarkerNotFound(Self::START_MARKER))?
+            + Self::START_MARKER.len();
+        let end = html[start..]
+            .find(Self::END_MARKER)
+            .ok_or(TrailDataError::MarkerNotFound(Self::END_MARKER))?
+            + start;
+
+        parse_trail_systems(&html[start..end])
+    }
+}
+
+// an api that returns the trail systems as JSON
+#[derive(Debug, Clone)]
+pub struct JsonApi {
+    pub url: String,
+}
+
+impl TrailDataSource for JsonApi {
+    fn name(&self) -> &'static str {
+        "json api"
+    }
+
+    async fn fetch(&self) -> Result<Vec<TrailSystem>, TrailDataError> {
+        parse_trail_systems(&get_text(&self.url).await?)
+    }
+}
+
+// a JSON file on disk, for development or when the website is down for good
+#[derive(Debug, Clone)]
+pub struct StaticFile {
+    pub path: PathBuf,
+}
+
+impl TrailDataSource for StaticFile {
+    fn name(&self) -> &'static str {
+        "static file"
+    }
+
+    async fn fetch(&self) -> Result<Vec<TrailSystem>, TrailDataError> {
+        let json = tokio::fs::read_to_string(&self.path)
+            .await
+            .map_err(TrailDataError::Io)?;
+        parse_trail_systems(&json)
+    }
+}
+
+// the source picked with TRAIL_DATA_SOURCE:
+//   scraper           the trail status website at TRAIL_DATA_URL (the default)
+//   json[:<url>]      a JSON api, TRAIL_DATA_URL when no url is given
+//   file:<path>       a JSON file
+#[derive(Debug, Clone)]
+pub enum ConfiguredSource {
+    Scraper(HtmlScraper),
+    Json(JsonApi),
+    File(StaticFile),
+}
+
+impl ConfiguredSource {
+    pub fn from_env() -> Result<Self, TrailDataError> {
+        let setting = env_utils::get_trail_data_source().unwrap_or("scraper".to_string());
+        let (kind, value) = match setting.split_once(':') {
+            Some((kind, value)) => (kind, Some(value.to_string())),
+            None => (setting.as_str(), None),
+        };
+
+        let url = || {
+            env_utils::get_trail_data_url()
+                .ok_or(TrailDataError::NotConfigured("TRAIL_DATA_URL".to_string()))
+        };
+
+        match (kind.to_lowercase().as_str(), value) {
+            ("scraper", _) => Ok(ConfiguredSource::Scraper(HtmlScraper { url: url()? })),
+            ("json", Some(url)) => Ok(ConfiguredSource::Json(JsonApi { url })),
+            ("json", None) => Ok(ConfiguredSource::Json(JsonApi { url: url()? })),
+            ("file", Some(path)) => Ok(ConfiguredSource::File(StaticFile {
+                path: PathBuf::from(path),
+            })),
+            _ => Err(TrailDataError::NotConfigured(format!(
+                "a valid TRAIL_DATA_SOURCE (got {setting})"
+            ))),
+        }
+    }
+}
+
+impl TrailDataSource for ConfiguredSource {
+    fn name(&self) -> &'static str {
+        match self {
+            ConfiguredSource::Scraper(source) => source.name(),
+            ConfiguredSource::Json(source) => source.name(),
+            ConfiguredSource::File(source) => source.name(),
+        }
+    }
+
+    async fn fetch(&self) -> Result<Vec<TrailSystem>, TrailDataError> {
+        match self {
+            ConfiguredSource::Scraper(source) => source.fetch().await,
+            ConfiguredSource::Json(source) => source.fetch().await,
+            ConfiguredSource::File(source) => source.fetch().await,
+        }
+    }
+}
+
+async fn get_text(url: &str) -> Result<String, TrailDataError> {
+    let resp = reqwest::get(url).await.map_err(TrailDataError::Request)?;
+    if !resp.status().is_success() {
+        return Err(TrailDataError::Status(resp.status()));
+    }
+
+    resp.text().await.map_err(TrailDataError::Request)
+}
+
+// a list of trail systems, or an object with them under `trail_systems`
+// entries that don't match the schema are skipped, as are repeats of an id that's already been seen,
+// but there has to be at least one valid one
+pub fn parse_trail_systems(json: &str) -> Result<Vec<TrailSystem>, TrailDataError> {
+    let value: serde_json::Value = serde_json::from_str(json).map_err(TrailDataError::Parse)?;
+    let entries = match value {
+        serde_json::Value::Array(entries) => entries,
+        serde_json::Value::Object(mut object) => match object.remove("trail_systems") {
+            Some(serde_json::Value::Array(entries)) => entries,
+            _ => {
+                return Err(TrailDataError::Invalid(
+                    "expected a list of trail systems".to_string(),
+                ))
+            }
+        },
+        _ => {
+            return Err(TrailDataError::Invalid(
+                "expected a list of trail systems".to_string(),
+            ))
+        }
+    };
+
+    let total = entries.len();
+    let mut seen_ids = HashSet::new();
+    let trail_systems: Vec<TrailSystem> = entries
+        .into_iter()
+        .enumerate()
+        .filter_map(
+            |(i, entry)| match serde_json::from_value::<TrailSystem>(entry) {
+                Ok(trail) => match validate(&trail) {
+                    Ok(()) if !seen_ids.insert(trail.id) => {
+                        tracing::warn!("Skipping trail system {}: duplicate id", trail.id);
+                        None
+                    }
+                    Ok(()) => Some(trail),
+                    Err(reason) => {
+                        tracing::warn!("Skipping trail system {}: {}", trail.id, reason);
+                        None
+                    }
+                },
+                Err(e) => {
+                    tracing::warn!("Skipping trail system at index {}: {}", i, e);
+                    None
+                }
+            },
+        )
+        .collect();
+
+    if trail_systems.is_empty() {
+        return Err(TrailDataError::Invalid(format!(
+            "none of the {total} trail systems are valid"
+        )));
+    }
+
+    Ok(trail_systems)
+}
+
+fn validate(trail: &TrailSystem) -> Result<(), String> {
+    if trail.id == 0 {
+        return Err("missing id".to_string());
+    }
+    if trail.name.trim().is_empty() {
+        return Err("missing name".to_string());
+    }
+    if !(-90.0..=90.0).contains(&trail.lat) || !(-180.0..=180.0).contains(&trail.lng) {
+        return Err(format!("invalid coordinates {}, {}", trail.lat, trail.lng));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{parse_trail_systems, TrailDataError};
+
+    fn trail_system(id: u64, lat: f64, lng: f64) -> serde_json::Value {
+        json!({
+            "id": id,
+            "status": "open",
+            "name": format!("Trail {id}"),
+            "city": "Fort Collins",
+            "state": "CO",
+            "lat": lat,
+            "lng": lng,
+            "total_distance": 12.5,
+            "status_description": "Open",
+        })
+    }
+
+    fn ids(json: serde_json::Value) -> Vec<u64> {
+        parse_trail_systems(&json.to_string())
+            .unwrap()
+            .iter()
+            .map(|trail| trail.id)
+            .collect()
+    }
+
+    #[test]
+    fn parses_a_list_or_an_object() {
+        let list = json!([trail_system(1, 40.5, -105.1), trail_system(2, 40.6, -105.2)]);
+        assert_eq!(ids(list.clone()), vec![1, 2]);
+        assert_eq!(ids(json!({ "trail_systems": list })), vec![1, 2]);
+    }
+
+    #[test]
+    fn malformed_json_is_a_parse_error() {
+        assert!(matches!(
+            parse_trail_systems("[{\"id\": 1,"),
+            Err(TrailDataError::Parse(_))
+        ));
+        assert!(matches!(
+            parse_trail_systems(""),
+            Err(TrailDataError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn json_that_isnt_a_list_is_invalid() {
+        for json in [json!("trails"), json!({ "trails": [] }), json!(42)] {
+            assert!(matches!(
+                parse_trail_systems(&json.to_string()),
+                Err(TrailDataError::Invalid(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn entries_without_an_id_are_skipped() {
+        let mut no_id = trail_system(0, 40.5, -105.1);
+        no_id.as_object_mut().unwrap().remove("id");
+        let json = json!([
+            no_id,
+            trail_system(0, 40.5, -105.1),
+            trail_system(2, 40.6, -105.2)
+        ]);
+        assert_eq!(ids(json), vec![2]);
+    }
+
+    #[test]
+    fn duplicate_ids_keep_the_first_entry() {
+        let json = json!([
+            trail_system(1, 40.5, -105.1),
+            trail_system(1, 41.0, -106.0),
+            trail_system(2, 40.6, -105.2)
+        ]);
+        let trails = parse_trail_systems(&json.to_string()).unwrap();
+        assert_eq!(trails.len(), 2);
+        assert_eq!(trails[0].lat, 40.5);
+    }
+
+    #[test]
+    fn out_of_range_coordinates_are_skipped() {
+        let json = json!([
+            trail_system(1, 91.0, -105.1),
+            trail_system(2, -90.5, -105.1),
+            trail_system(3, 40.5, 180.5),
+            trail_system(4, 40.5, -181.0),
+            trail_system(5, 90.0, -180.0)
+        ]);
+        assert_eq!(ids(json), vec![5]);
+    }
+
+    #[test]
+    fn no_valid_entries_is_invalid() {
+        let json = json!([trail_system(1, 100.0, 0.0), { "id": 2 }]);
+        assert!(matches!(
+            parse_trail_systems(&json.to_string()),
+            Err(TrailDataError::Invalid(_))
+        ));
+        assert!(matches!(
+            parse_trail_systems("[]"),
+            Err(TrailDataError::Invalid(_))
+        ));
+    }
+}
@@ -49,7 +49,10 @@ async fn main() -> anyhow::Result<()> {
         db.init_tables().await;
     }
 
+    beacon_service::riders::sync_from_config().await;
+
     beacon_service::beacon_loop::start();
+    beacon_service::trail_watch::start();
 
     let port = crate::env_utils::get_port();
     let addr = format!("[::]:{port}")
@@ -79,7 +82,7 @@ async fn main() -> anyhow::Result<()> {
                 .layer(TraceLayer::new_for_http().on_response(
                     |response: &Response, latency: std::time::Duration, _span: &tracing::Span| {
                         let url = match response.extensions().get::<RequestUri>().map(|r| &r.0) {
-                            Some(uri) => uri.to_string(),
+                            Some(uri) => loggable_path(uri),
                             None => "unknown".to_string(),
                         };
                         let status = response.status();
@@ -102,6 +105,18 @@ async fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
+// the request path without its query string, and with the secret segment of webhook routes masked
+fn loggable_path(uri: &Uri) -> String {
+    let path = uri.path();
+    match path
+        .strip_prefix("/wh/")
+        .and_then(|rest| rest.split_once('/'))
+    {
+        Some((route, _)) => format!("/wh/{route}/***"),
+        None => path.to_string(),
+    }
+}
+
 /**
  * main router for the app, defines basic root routes including the webhook event route
  * also brings together the other routers
@@ -111,16 +126,16 @@ fn get_main_router() -> Router {
 
     let wh_secret = crate::env_utils::get_webhook_secret();
     let wh_path = format!("/wh/trail-event/{wh_secret:#}");
-    tracing::info!("Webhook event route: {}", wh_path);
+    let inbound_path = format!("/wh/inbound/{wh_secret:#}");
 
     let services_router = get_services_router();
     let api_router = get_api_router();
     Router::new()
         .route("/", get(route_handlers::home::handler))
-        .route(
-            &wh_path,
-            post(route_handlers::webhooks::handler).get(route_handlers::webhooks::handler),
-        )
+        .route("/rides", get(route_handlers::rides::handler))
+        .route("/trails/:id", get(route_handlers::trail::handler))
+        .route(&wh_path, post(route_handlers::webhooks::handler))
+        .route(&inbound_path, post(route_handlers::inbound::handler))
         .route("/healthcheck", get(|| async { "Ok" }))
         .merge(services_router)
         .nest("/api", api_router)
@@ -156,6 +171,23 @@ fn get_api_router() -> Router {
             get(route_handlers::trail_ride_counts::handler),
         )
         .route("/troy-check", get(route_handlers::troy_check::handler))
+        .route(
+            "/rides/:id/elevation-profile.png",
+            get(route_handlers::elevation_profile::handler),
+        )
+        .route(
+            "/rides/:id/card.png",
+            get(route_handlers::ride_card::handler),
+        )
+        .route(
+            "/rides/:id/export/:format",
+            get(route_handlers::ride_export::handler),
+        )
+        .route(
+            "/beacon-tracks/:id/export/:format",
+            get(route_handlers::beacon_track_export::handler),
+        )
+        .route("/heatmap", get(route_handlers::heatmap::handler))
         .nest(
             "/strava",
             Router::new()
@@ -0,0 +1,75 @@
+use std::time::Duration;
+
+use axum::extract::{Path, Query};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::IntoResponse;
+use serde::Deserialize;
+
+use shared_lib::track_export::{ExportFormat, Track};
+
+use super::rate_limit::RateLimit;
+use super::ride_export::PRIVACY_RADIUS_METERS;
+
+const TOKEN_HEADER: &str = "X-Export-Token";
+
+static EXPORT_LIMIT: RateLimit = RateLimit::new(30, Duration::from_secs(60));
+
+#[derive(Deserialize, Debug)]
+pub struct BeaconTrackExportParams {
+    // for clients that can't set the X-Export-Token header
+    token: Option<String>,
+}
+
+// the breadcrumbs recorded from a live beacon, available before the ride is uploaded
+// these show where someone is right now, so they're only handed out with EXPORT_TOKEN
+pub async fn handler(
+    Path((live_activity_id, format)): Path<(i64, String)>,
+    Query(params): Query<BeaconTrackExportParams>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let expected = match shared_lib::env_utils::get_export_token() {
+        Some(token) => token,
+        None => return StatusCode::NOT_FOUND.into_response(),
+    };
+    let token = headers
+        .get(TOKEN_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .or(params.token.as_deref());
+    if !token.is_some_and(|token| shared_lib::utils::tokens_match(token, &expected)) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
+    if !EXPORT_LIMIT.try_acquire() {
+        return StatusCode::TOO_MANY_REQUESTS.into_response();
+    }
+
+    let format = match ExportFormat::from_name(&format) {
+        Some(format) => format,
+        None => return StatusCode::NOT_FOUND.into_response(),
+    };
+
+    let points = db_service::beacon_tracks::get_track(live_activity_id).await;
+    let track = Track {
+        name: format!("Live ride {live_activity_id}"),
+        points,
+    }
+    .trim_ends(PRIVACY_RADIUS_METERS);
+    if track.points.is_empty() {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+
+    let disposition = format!(
+        "attachment; filename=\"beacon-{}.{}\"",
+        live_activity_id,
+        format.extension()
+    );
+    (
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+            (header::CACHE_CONTROL, "private, no-store".to_string()),
+        ],
+        track.export(format),
+    )
+        .into_response()
+}
@@ -0,0 +1,120 @@
+use std::collections::VecDeque;
+use std::sync::{LazyLock, Mutex};
+
+use axum::extract::{Path, Query};
+use axum::http::{header, StatusCode};
+use axum::response::IntoResponse;
+use serde::Deserialize;
+
+use map_service::chart::{self, ProfileData};
+
+const DEFAULT_WIDTH: u32 = 900;
+const DEFAULT_HEIGHT: u32 = 300;
+
+// rendered charts, most recently used last, so repeat requests (e.g. per theme) don't render again
+const MAX_CACHED_PROFILES: usize = 50;
+
+#[derive(Debug, Clone, PartialEq)]
+struct ProfileKey {
+    activity_id: i64,
+    speed: bool,
+    width: u32,
+    height: u32,
+    theme: Option<String>,
+}
+
+type ProfileCache = VecDeque<(ProfileKey, Vec<u8>)>;
+
+static CACHE_PROFILES: LazyLock<Mutex<ProfileCache>> =
+    LazyLock::new(|| Mutex::new(VecDeque::new()));
+
+#[derive(Deserialize, Debug)]
+pub struct ElevationProfileParams {
+    #[serde(default)]
+    speed: bool,
+    width: Option<u32>,
+    height: Option<u32>,
+    theme: Option<String>,
+}
+
+pub async fn handler(
+    Path(activity_id): Path<i64>,
+    Query(params): Query<ElevationProfileParams>,
+) -> impl IntoResponse {
+    let key = ProfileKey {
+        activity_id,
+        speed: params.speed,
+        width: params.width.unwrap_or(DEFAULT_WIDTH).clamp(300, 2000),
+        height: params.height.unwrap_or(DEFAULT_HEIGHT).clamp(120, 1000),
+        theme: params.theme,
+    };
+
+    if let Some(png) = get_cached_profile(&key) {
+        return png_response(png);
+    }
+
+    // only the athlete's own rides, so this can't be used to burn through the strava rate limit
+    match strava_service::is_known_activity(activity_id).await {
+        Ok(true) => {}
+        Ok(false) => return StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            tracing::error!("Failed to get activities: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    }
+
+    let streams = match strava_service::get_activity_streams(activity_id).await {
+        Ok(streams) => streams,
+        Err(e) => {
+            tracing::error!("Failed to get activity streams: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let profile = match ProfileData::from_streams(&streams, key.speed) {
+        Some(profile) => profile,
+        None => return StatusCode::NOT_FOUND.into_response(),
+    };
+
+    let theme = map_service::theme::get_theme(key.theme.as_deref());
+
+    match chart::render_profile_png(&profile, key.width, key.height, &theme) {
+        Ok(png) => {
+            cache_profile(key, png.clone());
+            png_response(png)
+        }
+        Err(e) => {
+            tracing::error!("Failed to render elevation profile: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+fn png_response(png: Vec<u8>) -> axum::response::Response {
+    (
+        [
+            (header::CONTENT_TYPE, "image/png"),
+            (header::CACHE_CONTROL, "public, max-age=86400"),
+        ],
+        png,
+    )
+        .into_response()
+}
+
+fn get_cached_profile(key: &ProfileKey) -> Option<Vec<u8>> {
+    let mut cache = CACHE_PROFILES.lock().unwrap();
+    let index = cache.iter().position(|(cached_key, _)| cached_key == key)?;
+    let entry = cache.remove(index)?;
+    let png = entry.1.clone();
+    cache.push_back(entry);
+    Some(png)
+}
+
+fn cache_profile(key: ProfileKey, png: Vec<u8>) {
+    let mut cache = CACHE_PROFILES.lock().unwrap();
+    cache.retain(|(cached_key, _)| *cached_key != key);
+    cache.push_back((key, png));
+    while cache.len() > MAX_CACHED_PROFILES {
+        cache.pop_front();
+    }
+}
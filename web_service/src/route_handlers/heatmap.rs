@@ -0,0 +1,177 @@
+use std::collections::VecDeque;
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::extract::Query;
+use axum::http::{header, StatusCode};
+use axum::response::IntoResponse;
+use serde::Deserialize;
+
+use map_service::heatmap::{self, BoundingBox, Heatmap};
+use map_service::output::{ImageSize, OutputFormat};
+use shared_lib::geofence::Geofence;
+
+use super::rate_limit::RateLimit;
+
+// a custom size could take hundreds of MB to draw, so only the presets are allowed
+const HEATMAP_SIZES: [ImageSize; 3] = [ImageSize::SQUARE, ImageSize::STORY, ImageSize::OPEN_GRAPH];
+
+// rendered heatmaps, most recently used last, new rides show up once the entry expires
+const MAX_CACHED_HEATMAPS: usize = 20;
+const HEATMAP_CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+
+// every render fetches tiles and reads all rides, cached heatmaps don't count towards this
+static RENDER_LIMIT: RateLimit = RateLimit::new(10, Duration::from_secs(60));
+
+#[derive(Debug, Clone, PartialEq)]
+struct HeatmapKey {
+    trail_id: Option<u64>,
+    radius: Option<u32>,
+    last: Option<usize>,
+    size: ImageSize,
+    format: OutputFormat,
+    theme: Option<String>,
+}
+
+type HeatmapCache = VecDeque<(HeatmapKey, Instant, Vec<u8>)>;
+
+static CACHE_HEATMAPS: LazyLock<Mutex<HeatmapCache>> =
+    LazyLock::new(|| Mutex::new(VecDeque::new()));
+
+#[derive(Deserialize, Debug)]
+pub struct HeatmapParams {
+    // only rides through this trail system, with the map zoomed in on its geofence
+    trail_id: Option<u64>,
+    // meters around the trail system to show instead of its geofence
+    radius: Option<f64>,
+    // only the most recent rides
+    last: Option<usize>,
+    size: Option<String>,
+    format: Option<String>,
+    theme: Option<String>,
+}
+
+pub async fn handler(Query(params): Query<HeatmapParams>) -> impl IntoResponse {
+    let key = HeatmapKey {
+        trail_id: params.trail_id,
+        radius: params
+            .radius
+            .map(|radius| radius.clamp(200.0, 50_000.0).round() as u32),
+        last: params.last,
+        size: params
+            .size
+            .as_deref()
+            .map(ImageSize::from)
+            .filter(|size| HEATMAP_SIZES.contains(size))
+            .unwrap_or_default(),
+        format: params
+            .format
+            .as_deref()
+            .map(OutputFormat::from)
+            .unwrap_or_default(),
+        theme: params.theme,
+    };
+
+    if let Some(image) = get_cached_heatmap(&key) {
+        return image_response(key.format, image);
+    }
+
+    if !RENDER_LIMIT.try_acquire() {
+        return StatusCode::TOO_MANY_REQUESTS.into_response();
+    }
+
+    let geofence = match key.trail_id {
+        Some(trail_id) => {
+            let trails = trail_service::trail_data::get_data().await.trail_data;
+            match trails.iter().find(|trail| trail.id == trail_id) {
+                Some(trail) => Some(Geofence::for_trail(trail)),
+                None => return StatusCode::NOT_FOUND.into_response(),
+            }
+        }
+        None => None,
+    };
+
+    let clip = geofence.as_ref().map(|geofence| match key.radius {
+        Some(radius) => BoundingBox::around(geofence.lat, geofence.lng, radius.into()),
+        None => {
+            let (min_lng, min_lat, max_lng, max_lat) = geofence.bounds();
+            BoundingBox {
+                min_lng,
+                min_lat,
+                max_lng,
+                max_lat,
+            }
+        }
+    });
+
+    let rides = match strava_service::get_all_activities().await {
+        Ok(rides) => rides,
+        Err(e) => {
+            tracing::error!("Failed to get rides for the heatmap: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    // rides come back newest first
+    let polylines: Vec<String> = rides
+        .into_iter()
+        .filter_map(|ride| ride.map.map(|map| map.summary_polyline))
+        .filter(|polyline| !polyline.is_empty())
+        .take(key.last.unwrap_or(usize::MAX))
+        .collect();
+
+    let mut heatmap = Heatmap::from_polylines(polylines.iter().map(String::as_str), clip);
+    if let Some(geofence) = &geofence {
+        heatmap = heatmap.with_outline(geofence.outline());
+    }
+    if heatmap.is_empty() {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    tracing::debug!("Drawing a heatmap of {} rides", heatmap.len());
+
+    let theme = map_service::theme::get_theme(key.theme.as_deref());
+
+    match heatmap::render_heatmap(heatmap, key.size, &theme, key.format).await {
+        Ok(image) => {
+            let format = key.format;
+            cache_heatmap(key, image.clone());
+            image_response(format, image)
+        }
+        Err(e) => {
+            tracing::error!("Failed to render heatmap: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+fn image_response(format: OutputFormat, image: Vec<u8>) -> axum::response::Response {
+    (
+        [
+            (header::CONTENT_TYPE, format.content_type()),
+            (header::CACHE_CONTROL, "public, max-age=3600"),
+        ],
+        image,
+    )
+        .into_response()
+}
+
+fn get_cached_heatmap(key: &HeatmapKey) -> Option<Vec<u8>> {
+    let mut cache = CACHE_HEATMAPS.lock().unwrap();
+    cache.retain(|(_, cached_at, _)| cached_at.elapsed() < HEATMAP_CACHE_TTL);
+    let index = cache
+        .iter()
+        .position(|(cached_key, _, _)| cached_key == key)?;
+    let entry = cache.remove(index)?;
+    let image = entry.2.clone();
+    cache.push_back(entry);
+    Some(image)
+}
+
+fn cache_heatmap(key: HeatmapKey, image: Vec<u8>) {
+    let mut cache = CACHE_HEATMAPS.lock().unwrap();
+    cache.retain(|(cached_key, _, _)| *cached_key != key);
+    cache.push_back((key, Instant::now(), image));
+    while cache.len() > MAX_CACHED_HEATMAPS {
+        cache.pop_front();
+    }
+}
@@ -1,12 +1,13 @@
 pub async fn handler() -> impl axum::response::IntoResponse {
-    let last_updated = match db_service::get_troy_status().await.trail_status_updated {
+    let rider_status = match db_service::riders::get_primary_rider().await {
+        Some(rider) => Some(db_service::riders::get_rider_status(rider.id).await),
+        None => None,
+    };
+
+    let last_updated = match rider_status.and_then(|status| status.trail_status_updated) {
         None => "never".to_string(),
         Some(last_updated) => {
-            let elapsed = last_updated.elapsed().unwrap();
-            if elapsed.as_secs() > 14400 {
-                db_service::set_troy_status(false).await;
-            }
-
+            let elapsed = last_updated.elapsed().unwrap_or_default();
             let elapsed = humantime::format_duration(elapsed).to_string();
             let elapsed = elapsed
                 .split_whitespace()
@@ -0,0 +1,381 @@
+use std::collections::HashMap;
+
+use axum::body::Bytes;
+use axum::extract::Query;
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use base64::Engine;
+use serde::Deserialize;
+
+use super::webhooks::WebhookError;
+
+const TOKEN_HEADER: &str = "X-Inbound-Token";
+
+// fields that carry the message text in the common inbound email/sms payloads
+// (mailgun, sendgrid, postmark, twilio and friends)
+const MESSAGE_FIELDS: [&str; 10] = [
+    "text",
+    "html",
+    "body-plain",
+    "body-html",
+    "stripped-text",
+    "TextBody",
+    "HtmlBody",
+    "plain",
+    "body",
+    "Body",
+];
+
+#[derive(Deserialize, Debug)]
+pub struct InboundParams {
+    // which rider the message is for, defaults to the primary rider
+    athlete_id: Option<i64>,
+    // for forwarders that can't set the X-Inbound-Token header
+    token: Option<String>,
+}
+
+// accepts a forwarded beacon email or sms and starts following the beacon url inside it
+pub async fn handler(
+    Query(params): Query<InboundParams>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    match handle_inbound_message(&params, &headers, &body).await {
+        Ok(()) => StatusCode::OK.into_response(),
+        Err(e) => {
+            tracing::warn!("Rejected inbound message: {:?}", e);
+            e.into_response()
+        }
+    }
+}
+
+async fn handle_inbound_message(
+    params: &InboundParams,
+    headers: &HeaderMap,
+    body: &[u8],
+) -> Result<(), WebhookError> {
+    verify_token(params, headers)?;
+
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_lowercase();
+
+    let messages = if content_type.starts_with("application/json") {
+        json_messages(body)
+    } else if content_type.starts_with("application/x-www-form-urlencoded") {
+        form_messages(body)
+    } else if content_type.starts_with("text/plain") {
+        vec![String::from_utf8_lossy(body).to_string()]
+    } else {
+        // anything else is treated as a raw (rfc 822) email, falling back to plain text
+        email_messages(body)
+    };
+
+    let beacon_url = messages
+        .iter()
+        .find_map(|message| strava_service::beacon::find_beacon_url(message))
+        .ok_or(WebhookError::BeaconUrlNotFound)?;
+
+    super::webhooks::start_beacon(params.athlete_id, beacon_url).await
+}
+
+// the path secret alone isn't enough, every message has to carry INBOUND_TOKEN too
+fn verify_token(params: &InboundParams, headers: &HeaderMap) -> Result<(), WebhookError> {
+    let expected =
+        shared_lib::env_utils::get_inbound_token().ok_or(WebhookError::TokenNotConfigured)?;
+
+    let token = headers
+        .get(TOKEN_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .or(params.token.as_deref())
+        .ok_or(WebhookError::InvalidToken)?;
+
+    match shared_lib::utils::tokens_match(token, &expected) {
+        true => Ok(()),
+        false => Err(WebhookError::InvalidToken),
+    }
+}
+
+fn json_messages(body: &[u8]) -> Vec<String> {
+    let payload: HashMap<String, serde_json::Value> = match serde_json::from_slice(body) {
+        Ok(payload) => payload,
+        Err(e) => {
+            tracing::debug!("Inbound message is not a JSON object: {}", e);
+            return Vec::new();
+        }
+    };
+
+    MESSAGE_FIELDS
+        .iter()
+        .filter_map(|field| payload.get(*field).and_then(|value| value.as_str()))
+        .map(|message| message.to_string())
+        .collect()
+}
+
+fn form_messages(body: &[u8]) -> Vec<String> {
+    let payload: HashMap<String, String> = match serde_urlencoded::from_bytes(body) {
+        Ok(payload) => payload,
+        Err(e) => {
+            tracing::debug!("Inbound message is not a form post: {}", e);
+            return Vec::new();
+        }
+    };
+
+    MESSAGE_FIELDS
+        .iter()
+        .filter_map(|field| payload.get(*field).cloned())
+        .collect()
+}
+
+// the decoded text of every part of a raw email, or the body as-is when it isn't an email
+fn email_messages(body: &[u8]) -> Vec<String> {
+    let text = String::from_utf8_lossy(body);
+    let mut messages = Vec::new();
+    match parse_mime_part(&text, 0, &mut messages) {
+        true => messages,
+        false => vec![text.to_string()],
+    }
+}
+
+// nested multiparts deeper than this are ignored
+const MAX_MIME_DEPTH: usize = 5;
+
+// decodes a mime entity (headers, blank line, body) into `messages`, descending into multiparts
+// returns false if the text doesn't start with headers
+fn parse_mime_part(part: &str, depth: usize, messages: &mut Vec<String>) -> bool {
+    let (headers, body) = match split_headers(part) {
+        Some(split) => split,
+        None => return false,
+    };
+
+    let content_type = headers.get("content-type").cloned().unwrap_or_default();
+    if content_type.to_lowercase().starts_with("multipart/") {
+        let boundary = match header_param(&content_type, "boundary") {
+            Some(boundary) => boundary,
+            None => return true,
+        };
+        if depth < MAX_MIME_DEPTH {
+            for sub_part in split_multipart(body, &boundary) {
+                parse_mime_part(sub_part, depth + 1, messages);
+            }
+        }
+        return true;
+    }
+
+    let encoding = headers
+        .get("content-transfer-encoding")
+        .map(|encoding| encoding.trim().to_lowercase());
+    let decoded = match encoding.as_deref() {
+        Some("quoted-printable") => decode_quoted_printable(body),
+        Some("base64") => {
+            let compact: String = body.split_whitespace().collect();
+            match base64::engine::general_purpose::STANDARD.decode(compact) {
+                Ok(bytes) => String::from_utf8_lossy(&bytes).to_string(),
+                Err(e) => {
+                    tracing::debug!("Failed to decode base64 email part: {}", e);
+                    return true;
+                }
+            }
+        }
+        _ => body.to_string(),
+    };
+    messages.push(decoded);
+    true
+}
+
+// splits off the header block, header names are lowercased and folded lines are joined
+fn split_headers(part: &str) -> Option<(HashMap<String, String>, &str)> {
+    let (header_block, body) = match (part.find("\r\n\r\n"), part.find("\n\n")) {
+        (Some(crlf), Some(lf)) if lf < crlf => (&part[..lf], &part[lf + 2..]),
+        (Some(crlf), _) => (&part[..crlf], &part[crlf + 4..]),
+        (None, Some(lf)) => (&part[..lf], &part[lf + 2..]),
+        (None, None) => (part, ""),
+    };
+
+    let mut headers: HashMap<String, String> = HashMap::new();
+    let mut last_name: Option<String> = None;
+    for line in header_block.lines() {
+        if line.starts_with([' ', '\t']) {
+            let name = last_name.as_ref()?;
+            if let Some(value) = headers.get_mut(name) {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+            continue;
+        }
+
+        let (name, value) = line.split_once(':')?;
+        let is_header_name =
+            !name.is_empty() && name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-');
+        if !is_header_name {
+            return None;
+        }
+        let name = name.to_lowercase();
+        headers.insert(name.clone(), value.trim().to_string());
+        last_name = Some(name);
+    }
+
+    match headers.is_empty() {
+        true => None,
+        false => Some((headers, body)),
+    }
+}
+
+// a parameter from a header value like `multipart/alternative; boundary="abc"`
+fn header_param(value: &str, name: &str) -> Option<String> {
+    value.split(';').skip(1).find_map(|param| {
+        let (key, value) = param.split_once('=')?;
+        match key.trim().eq_ignore_ascii_case(name) {
+            true => Some(value.trim().trim_matches('"').to_string()),
+            false => None,
+        }
+    })
+}
+
+// the parts between the boundary lines, without the preamble and epilogue
+fn split_multipart<'a>(body: &'a str, boundary: &str) -> Vec<&'a str> {
+    let delimiter = format!("--{boundary}");
+    let mut sections = body.split(delimiter.as_str());
+    // the preamble before the first boundary isn't a part
+    sections.next();
+
+    sections
+        .take_while(|section| !section.starts_with("--"))
+        .map(|section| section.trim_start_matches(['\r', '\n']))
+        .collect()
+}
+
+// undoes quoted-printable soft line breaks and =XX escapes, which break up urls in raw emails
+fn decode_quoted_printable(text: &str) -> String {
+    let text = text.replace("=\r\n", "").replace("=\n", "");
+    let bytes = text.as_bytes();
+
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = match bytes.get(i + 1..i + 3) {
+            Some(hex) if bytes[i] == b'=' && hex.iter().all(|b| b.is_ascii_hexdigit()) => {
+                std::str::from_utf8(hex)
+                    .ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            }
+            _ => None,
+        };
+
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{email_messages, form_messages, json_messages};
+
+    const BEACON_URL: &str = "https://www.strava.com/beacon/abc123?x=1&y=2";
+
+    fn find_beacon_url(messages: &[String]) -> Option<String> {
+        messages
+            .iter()
+            .find_map(|message| strava_service::beacon::find_beacon_url(message))
+    }
+
+    #[test]
+    fn json_messages_reads_known_fields() {
+        let body = serde_json::json!({
+            "subject": "ignored",
+            "TextBody": format!("Follow along: {BEACON_URL}"),
+        });
+        let messages = json_messages(body.to_string().as_bytes());
+        assert_eq!(messages.len(), 1);
+        assert_eq!(find_beacon_url(&messages).as_deref(), Some(BEACON_URL));
+    }
+
+    #[test]
+    fn json_messages_ignores_invalid_json() {
+        assert!(json_messages(b"not json").is_empty());
+        assert!(json_messages(b"[\"text\"]").is_empty());
+    }
+
+    #[test]
+    fn form_messages_reads_known_fields() {
+        let body = serde_urlencoded::to_string([
+            ("From", "+15555555555"),
+            ("Body", &format!("Troy is riding {BEACON_URL}")),
+        ])
+        .unwrap();
+        let messages = form_messages(body.as_bytes());
+        assert_eq!(find_beacon_url(&messages).as_deref(), Some(BEACON_URL));
+    }
+
+    #[test]
+    fn plain_text_is_not_decoded() {
+        let url = "https://www.strava.com/beacon/abc123?a=3D1";
+        let messages = email_messages(format!("See {url}").as_bytes());
+        assert_eq!(find_beacon_url(&messages).as_deref(), Some(url));
+    }
+
+    #[test]
+    fn email_decodes_quoted_printable_parts() {
+        let email = "From: strava@strava.com\r\n\
+            Subject: Beacon\r\n\
+            Content-Type: text/plain; charset=utf-8\r\n\
+            Content-Transfer-Encoding: quoted-printable\r\n\
+            \r\n\
+            Follow along: https://www.strava.com/beacon/abc123?x=3D1&y=\r\n=3D2\r\n";
+        let messages = email_messages(email.as_bytes());
+        assert_eq!(find_beacon_url(&messages).as_deref(), Some(BEACON_URL));
+    }
+
+    #[test]
+    fn email_only_decodes_marked_parts() {
+        let email = "From: strava@strava.com\n\
+            Content-Type: text/plain\n\
+            \n\
+            https://www.strava.com/beacon/abc123?a=3D1\n";
+        let messages = email_messages(email.as_bytes());
+        assert_eq!(
+            find_beacon_url(&messages).as_deref(),
+            Some("https://www.strava.com/beacon/abc123?a=3D1")
+        );
+    }
+
+    #[test]
+    fn email_decodes_base64_multipart() {
+        use base64::Engine;
+        let encoded = base64::engine::general_purpose::STANDARD
+            .encode(format!("<a href=\"{BEACON_URL}\">Follow along</a>"));
+        let email = format!(
+            "From: strava@strava.com\r\n\
+            Content-Type: multipart/alternative;\r\n boundary=\"b1\"\r\n\
+            \r\n\
+            preamble\r\n\
+            --b1\r\n\
+            Content-Type: text/plain\r\n\
+            \r\n\
+            No link here\r\n\
+            --b1\r\n\
+            Content-Type: text/html\r\n\
+            Content-Transfer-Encoding: base64\r\n\
+            \r\n\
+            {}\r\n{}\r\n\
+            --b1--\r\n",
+            &encoded[..20],
+            &encoded[20..]
+        );
+        let messages = email_messages(email.as_bytes());
+        assert_eq!(messages.len(), 2);
+        assert_eq!(find_beacon_url(&messages).as_deref(), Some(BEACON_URL));
+    }
+}
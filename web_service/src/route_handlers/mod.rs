@@ -1,8 +1,17 @@
+pub mod beacon_track_export;
+pub mod elevation_profile;
+pub mod heatmap;
 pub mod home;
 pub mod html_template;
+pub mod inbound;
+pub mod rate_limit;
+pub mod ride_card;
+pub mod ride_export;
+pub mod rides;
 pub mod strava_auth;
 pub mod strava_callback;
 pub mod strava_data;
+pub mod trail;
 pub mod trail_check;
 pub mod trail_ride_counts;
 pub mod troy_check;
@@ -0,0 +1,39 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// a fixed window request limit shared by every client, enough to keep a scraper from hammering an endpoint
+pub struct RateLimit {
+    max_requests: u32,
+    window: Duration,
+    // when the current window started and how many requests it has had
+    state: Mutex<Option<(Instant, u32)>>,
+}
+
+impl RateLimit {
+    pub const fn new(max_requests: u32, window: Duration) -> Self {
+        RateLimit {
+            max_requests,
+            window,
+            state: Mutex::new(None),
+        }
+    }
+
+    // counts the request, false if the window's limit has been reached
+    pub fn try_acquire(&self) -> bool {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+
+        let (window_start, count) = match *state {
+            Some((window_start, count)) if now - window_start < self.window => {
+                (window_start, count)
+            }
+            _ => (now, 0),
+        };
+        if count >= self.max_requests {
+            return false;
+        }
+
+        *state = Some((window_start, count + 1));
+        true
+    }
+}
@@ -0,0 +1,39 @@
+use axum::extract::{Path, Query};
+use axum::http::{header, StatusCode};
+use axum::response::IntoResponse;
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug)]
+pub struct RideCardParams {
+    // a specific variant, the newest card is served without one
+    v: Option<String>,
+}
+
+pub async fn handler(
+    Path(activity_id): Path<i64>,
+    Query(params): Query<RideCardParams>,
+) -> impl IntoResponse {
+    // a variant never changes once rendered, the newest card can be replaced by a new variant
+    // old variants are deleted when a new one is rendered, links to them get the newest card instead
+    let (card, cache_control) =
+        match db_service::ride_cards::get_card(activity_id, params.v.as_deref()).await {
+            Some(card) if params.v.is_some() => (card, "public, max-age=31536000, immutable"),
+            Some(card) => (card, "public, max-age=3600"),
+            None if params.v.is_some() => {
+                match db_service::ride_cards::get_card(activity_id, None).await {
+                    Some(card) => (card, "public, max-age=3600"),
+                    None => return StatusCode::NOT_FOUND.into_response(),
+                }
+            }
+            None => return StatusCode::NOT_FOUND.into_response(),
+        };
+
+    (
+        [
+            (header::CONTENT_TYPE, card.content_type),
+            (header::CACHE_CONTROL, cache_control.to_string()),
+        ],
+        card.image,
+    )
+        .into_response()
+}
@@ -0,0 +1,96 @@
+use std::time::Duration;
+
+use axum::extract::Path;
+use axum::http::{header, StatusCode};
+use axum::response::IntoResponse;
+use chrono::{DateTime, Utc};
+
+use shared_lib::track_export::{ExportFormat, Track};
+
+use super::rate_limit::RateLimit;
+
+// the start and end of a public export are cut off so it doesn't lead back to anyone's house
+pub const PRIVACY_RADIUS_METERS: f64 = 400.0;
+
+static EXPORT_LIMIT: RateLimit = RateLimit::new(30, Duration::from_secs(60));
+
+// exports the ride with elevation and times from its streams when strava has them, otherwise its
+// summary polyline from the cached activity list, either way with the ends trimmed off
+pub async fn handler(Path((activity_id, format)): Path<(i64, String)>) -> impl IntoResponse {
+    let format = match ExportFormat::from_name(&format) {
+        Some(format) => format,
+        None => return StatusCode::NOT_FOUND.into_response(),
+    };
+
+    if !EXPORT_LIMIT.try_acquire() {
+        return StatusCode::TOO_MANY_REQUESTS.into_response();
+    }
+
+    // only the athlete's own rides, so this can't be used to fetch streams for any activity
+    let activity = match strava_service::get_known_activity(activity_id).await {
+        Ok(Some(activity)) => activity,
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            tracing::error!("Failed to get activities: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let start_time = activity
+        .start_date
+        .as_deref()
+        .and_then(|date| DateTime::parse_from_rfc3339(date).ok())
+        .map(|date| date.with_timezone(&Utc));
+    let from_streams = match strava_service::get_activity_streams(activity_id).await {
+        Ok(streams) => Track::from_streams(&activity.name, &streams, start_time),
+        Err(e) => {
+            tracing::warn!(
+                "Failed to get streams for ride {}, exporting its polyline: {}",
+                activity_id,
+                e
+            );
+            None
+        }
+    };
+
+    let track = match from_streams {
+        Some(track) => track,
+        None => {
+            let polyline = activity
+                .map
+                .map(|map| map.summary_polyline)
+                .unwrap_or_default();
+            match Track::from_polyline(&activity.name, &polyline) {
+                Ok(track) => track,
+                Err(e) => {
+                    tracing::error!(
+                        "Failed to decode polyline for ride {}: {:?}",
+                        activity_id,
+                        e
+                    );
+                    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+                }
+            }
+        }
+    }
+    .trim_ends(PRIVACY_RADIUS_METERS);
+
+    if track.points.is_empty() {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+
+    let disposition = format!(
+        "attachment; filename=\"ride-{}.{}\"",
+        activity_id,
+        format.extension()
+    );
+    (
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+            (header::CACHE_CONTROL, "public, max-age=3600".to_string()),
+        ],
+        track.export(format),
+    )
+        .into_response()
+}
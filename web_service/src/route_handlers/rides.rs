@@ -0,0 +1,51 @@
+use std::time::{Duration, SystemTime};
+
+const RIDES_SHOWN: i64 = 30;
+
+pub async fn handler() -> impl axum::response::IntoResponse {
+    // only the strava token's rider has rides that can be exported, cards from before
+    // multiple riders have no rider and belong to them too
+    let strava_rider_id = db_service::riders::get_strava_rider()
+        .await
+        .map(|rider| rider.id);
+
+    let rides = db_service::ride_cards::get_recent_cards(RIDES_SHOWN)
+        .await
+        .into_iter()
+        .map(|card| {
+            let ride_date = card.started_at.unwrap_or(card.created_at);
+            let ride_date = SystemTime::UNIX_EPOCH + Duration::from_secs(ride_date as u64);
+            let date = humantime::format_rfc3339_seconds(ride_date).to_string();
+
+            let is_exportable = card.rider_id.is_none() || card.rider_id == strava_rider_id;
+
+            RideDisplay {
+                card_url: format!(
+                    "/api/rides/{}/card.png?v={}",
+                    card.activity_id, card.variant
+                ),
+                gpx_url: is_exportable
+                    .then(|| format!("/api/rides/{}/export/gpx", card.activity_id)),
+                title: card.title.unwrap_or("Ride".to_string()),
+                rider_name: card.rider_name.unwrap_or_default(),
+                date: date[0..10].to_string(),
+            }
+        })
+        .collect();
+
+    super::html_template::HtmlTemplate(RidesTemplate { rides })
+}
+
+struct RideDisplay {
+    card_url: String,
+    gpx_url: Option<String>,
+    title: String,
+    rider_name: String,
+    date: String,
+}
+
+#[derive(askama::Template)]
+#[template(path = "pages/rides.html")]
+struct RidesTemplate {
+    rides: Vec<RideDisplay>,
+}
@@ -0,0 +1,56 @@
+use std::time::{Duration, SystemTime};
+
+use axum::extract::Path;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+
+use shared_lib::trail_structs::{TrailStatusStats, TrailSystem};
+
+const HISTORY_SHOWN: usize = 20;
+
+pub async fn handler(Path(trail_id): Path<u64>) -> impl IntoResponse {
+    let trails = trail_service::trail_data::get_data().await.trail_data;
+    let trail = match trails.into_iter().find(|trail| trail.id == trail_id) {
+        Some(trail) => trail,
+        None => return StatusCode::NOT_FOUND.into_response(),
+    };
+
+    let status_stats = trail_service::status_history::get_stats(trail_id).await;
+    let history = trail_service::status_history::get_history(trail_id)
+        .await
+        .into_iter()
+        .rev()
+        .take(HISTORY_SHOWN)
+        .map(|change| {
+            let changed_at = SystemTime::UNIX_EPOCH + Duration::from_secs(change.changed_at as u64);
+            let date = humantime::format_rfc3339_seconds(changed_at).to_string();
+
+            StatusChangeDisplay {
+                date: date[0..10].to_string(),
+                status: change.status.to_string(),
+                status_description: change.status_description.unwrap_or_default(),
+            }
+        })
+        .collect();
+
+    super::html_template::HtmlTemplate(TrailTemplate {
+        trail,
+        status_stats,
+        history,
+    })
+    .into_response()
+}
+
+struct StatusChangeDisplay {
+    date: String,
+    status: String,
+    status_description: String,
+}
+
+#[derive(askama::Template)]
+#[template(path = "pages/trail.html")]
+struct TrailTemplate {
+    trail: TrailSystem,
+    status_stats: Option<TrailStatusStats>,
+    history: Vec<StatusChangeDisplay>,
+}
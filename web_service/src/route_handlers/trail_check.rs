@@ -2,16 +2,22 @@ use shared_lib::trail_structs::{TrailStatus, TrailSystem};
 
 pub async fn handler() -> impl axum::response::IntoResponse {
     let trail_data_cache = trail_service::trail_data::get_data().await;
+    let status_stats = trail_service::status_history::get_all_stats().await;
+    let trails = trail_data_cache
+        .trail_data
+        .into_iter()
+        .map(|mut trail| {
+            trail.status_stats = status_stats.get(&trail.id).copied();
+            trail
+        })
+        .collect::<Vec<TrailSystem>>();
+
     let trails = match strava_service::get_cached_activities(None).await {
-        None => trail_data_cache.trail_data,
+        None => trails,
         Some(rides) => {
-            let trail_stats = trail_service::ride_counts::calculate_stats(
-                trail_data_cache.trail_data.clone(),
-                rides,
-            );
+            let trail_stats = trail_service::ride_counts::calculate_stats(trails.clone(), rides);
 
-            trail_data_cache
-                .trail_data
+            trails
                 .into_iter()
                 .map(|mut trail| {
                     if let Some(stats) = trail_stats.get(&trail.id) {
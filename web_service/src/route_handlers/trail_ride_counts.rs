@@ -1,3 +1,6 @@
+use std::collections::HashMap;
+
+use map_service::route_svg::{self, SvgOptions};
 use shared_lib::trail_structs::TrailStatsDisplay;
 
 pub async fn handler() -> impl axum::response::IntoResponse {
@@ -6,6 +9,11 @@ pub async fn handler() -> impl axum::response::IntoResponse {
         .await
         .unwrap_or_default();
 
+    let polylines: HashMap<i64, String> = rides
+        .iter()
+        .filter_map(|ride| Some((ride.id, ride.map.as_ref()?.summary_polyline.clone())))
+        .collect();
+
     let trail_stats =
         trail_service::ride_counts::calculate_stats(trail_data_cache.trail_data, rides);
 
@@ -13,8 +21,14 @@ pub async fn handler() -> impl axum::response::IntoResponse {
         .into_iter()
         .filter(|(_, stats)| stats.rides != 0)
         .map(|(_, stats)| {
+            let mut display: TrailStatsDisplay = stats.into();
+            display.route_svg = stats
+                .last_ride_id
+                .and_then(|id| polylines.get(&id))
+                .and_then(|polyline| route_thumbnail(polyline));
+
             let template = TrailStatsTemplate {
-                stats: stats.into(),
+                stats: display,
                 swap_oob: true,
             };
             template.to_string()
@@ -30,3 +44,20 @@ struct TrailStatsTemplate {
     pub stats: TrailStatsDisplay,
     pub swap_oob: bool,
 }
+
+fn route_thumbnail(polyline: &str) -> Option<String> {
+    let options = SvgOptions {
+        width: 40,
+        height: 28,
+        padding: 2,
+        ..Default::default()
+    };
+
+    match route_svg::render_route_svg(polyline, &options) {
+        Ok(svg) => Some(svg),
+        Err(e) => {
+            tracing::debug!("Failed to draw route thumbnail: {:?}", e);
+            None
+        }
+    }
+}
@@ -1,13 +1,56 @@
 pub async fn handler() -> impl axum::response::IntoResponse {
-    let troy_status = db_service::get_troy_status().await;
+    let riders_on_trail = db_service::riders::get_riders_on_trail().await;
+    let trails = trail_service::trail_data::get_data().await.trail_data;
+
+    let riders = riders_on_trail
+        .into_iter()
+        .map(|(rider, status)| RiderOnTrail {
+            name: rider.name,
+            trail_name: status.current_trail_id.and_then(|trail_id| {
+                trails
+                    .iter()
+                    .find(|trail| trail.id == trail_id)
+                    .map(|trail| trail.name.clone())
+            }),
+        })
+        .collect::<Vec<_>>();
+
+    let marquee_text = match riders.as_slice() {
+        [rider] => format!("{} IS ON THE TRAILS", rider.name.to_uppercase()),
+        _ => format!(
+            "{} ARE ON THE TRAILS",
+            riders
+                .iter()
+                .map(|rider| rider.name.to_uppercase())
+                .collect::<Vec<_>>()
+                .join(" & ")
+        ),
+    };
+
+    // with a single registered rider the page reads the same as it always has
+    let registered_riders = db_service::riders::get_riders().await;
+    let nobody_out_text = match registered_riders.as_slice() {
+        [rider] => format!("{} is not currently on the trails.", rider.name),
+        _ => "Nobody is currently on the trails.".to_string(),
+    };
+
     let template = TrailCheckTemplate {
-        is_troy_on_the_trails: troy_status.is_on_trail,
+        riders,
+        marquee_text,
+        nobody_out_text,
     };
     super::html_template::HtmlTemplate(template)
 }
 
+struct RiderOnTrail {
+    name: String,
+    trail_name: Option<String>,
+}
+
 #[derive(askama::Template)]
 #[template(path = "components/troy_check.html")]
 struct TrailCheckTemplate {
-    is_troy_on_the_trails: bool,
+    riders: Vec<RiderOnTrail>,
+    marquee_text: String,
+    nobody_out_text: String,
 }
@@ -1,13 +1,227 @@
+use std::time::SystemTime;
+
+use axum::body::Bytes;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
 use axum::Json;
 use serde::Deserialize;
 
+const SIGNATURE_HEADER: &str = "X-Signature";
+const TIMESTAMP_HEADER: &str = "X-Timestamp";
+
+// how far a request's timestamp can be from our clock before it's rejected
+const TIMESTAMP_TOLERANCE_SECS: i64 = 300;
+
 #[derive(Deserialize, Debug)]
 pub struct WebhookRequest {
     beacon_url: String,
+    // which rider the beacon belongs to, defaults to the primary rider
+    athlete_id: Option<i64>,
+}
+
+#[derive(Debug)]
+pub enum WebhookError {
+    SigningNotConfigured,
+    MissingHeader(&'static str),
+    InvalidTimestamp,
+    StaleTimestamp,
+    InvalidSignature,
+    ReplayedRequest,
+    ReplayCheckFailed,
+    TokenNotConfigured,
+    InvalidToken,
+    MalformedPayload(String),
+    InvalidBeaconUrl,
+    BeaconUrlNotFound,
+    UnknownRider,
+}
+
+impl WebhookError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            WebhookError::SigningNotConfigured
+            | WebhookError::ReplayCheckFailed
+            | WebhookError::TokenNotConfigured => StatusCode::SERVICE_UNAVAILABLE,
+            WebhookError::MissingHeader(_)
+            | WebhookError::InvalidTimestamp
+            | WebhookError::MalformedPayload(_) => StatusCode::BAD_REQUEST,
+            WebhookError::StaleTimestamp
+            | WebhookError::InvalidSignature
+            | WebhookError::ReplayedRequest
+            | WebhookError::InvalidToken => StatusCode::UNAUTHORIZED,
+            WebhookError::InvalidBeaconUrl | WebhookError::BeaconUrlNotFound => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            WebhookError::UnknownRider => StatusCode::NOT_FOUND,
+        }
+    }
+
+    fn code(&self) -> &'static str {
+        match self {
+            WebhookError::SigningNotConfigured => "signing_not_configured",
+            WebhookError::MissingHeader(_) => "missing_header",
+            WebhookError::InvalidTimestamp => "invalid_timestamp",
+            WebhookError::StaleTimestamp => "stale_timestamp",
+            WebhookError::InvalidSignature => "invalid_signature",
+            WebhookError::ReplayedRequest => "replayed_request",
+            WebhookError::ReplayCheckFailed => "replay_check_failed",
+            WebhookError::TokenNotConfigured => "token_not_configured",
+            WebhookError::InvalidToken => "invalid_token",
+            WebhookError::MalformedPayload(_) => "malformed_payload",
+            WebhookError::InvalidBeaconUrl => "invalid_beacon_url",
+            WebhookError::BeaconUrlNotFound => "beacon_url_not_found",
+            WebhookError::UnknownRider => "unknown_rider",
+        }
+    }
+
+    fn message(&self) -> String {
+        match self {
+            WebhookError::SigningNotConfigured => {
+                "Webhook signing secret is not configured".to_string()
+            }
+            WebhookError::MissingHeader(header) => format!("Missing {header} header"),
+            WebhookError::InvalidTimestamp => {
+                format!("{TIMESTAMP_HEADER} must be a unix timestamp in seconds")
+            }
+            WebhookError::StaleTimestamp => format!(
+                "{TIMESTAMP_HEADER} is more than {TIMESTAMP_TOLERANCE_SECS} seconds from the server time"
+            ),
+            WebhookError::InvalidSignature => "Signature does not match the request".to_string(),
+            WebhookError::ReplayedRequest => "Request has already been received".to_string(),
+            WebhookError::ReplayCheckFailed => {
+                "Could not check the request against previous requests".to_string()
+            }
+            WebhookError::TokenNotConfigured => "Inbound token is not configured".to_string(),
+            WebhookError::InvalidToken => "Missing or invalid inbound token".to_string(),
+            WebhookError::MalformedPayload(reason) => format!("Malformed payload: {reason}"),
+            WebhookError::InvalidBeaconUrl => "beacon_url must be a Strava beacon url".to_string(),
+            WebhookError::BeaconUrlNotFound => "No Strava beacon url found in the message".to_string(),
+            WebhookError::UnknownRider => "No rider found for the request".to_string(),
+        }
+    }
 }
 
-pub async fn handler(Json(payload): Json<WebhookRequest>) -> impl axum::response::IntoResponse {
+impl IntoResponse for WebhookError {
+    fn into_response(self) -> Response {
+        let body = serde_json::json!({
+            "error": self.code(),
+            "message": self.message(),
+        });
+        (self.status_code(), Json(body)).into_response()
+    }
+}
+
+pub async fn handler(headers: HeaderMap, body: Bytes) -> Response {
+    match handle_trail_event(&headers, &body).await {
+        Ok(()) => StatusCode::OK.into_response(),
+        Err(e) => {
+            tracing::warn!("Rejected webhook request: {:?}", e);
+            e.into_response()
+        }
+    }
+}
+
+async fn handle_trail_event(headers: &HeaderMap, body: &[u8]) -> Result<(), WebhookError> {
+    verify_signature(headers, body).await?;
+
+    let payload: WebhookRequest =
+        serde_json::from_slice(body).map_err(|e| WebhookError::MalformedPayload(e.to_string()))?;
     tracing::debug!("Webhook request: {:?}", payload);
-    db_service::set_beacon_url(Some(payload.beacon_url)).await;
-    axum::http::status::StatusCode::OK
+
+    if !strava_service::beacon::is_beacon_url(&payload.beacon_url) {
+        return Err(WebhookError::InvalidBeaconUrl);
+    }
+
+    start_beacon(payload.athlete_id, payload.beacon_url).await
+}
+
+// hands a new beacon url to the rider's beacon loop, the rider defaults to the primary rider
+pub async fn start_beacon(athlete_id: Option<i64>, beacon_url: String) -> Result<(), WebhookError> {
+    let rider = match athlete_id {
+        Some(athlete_id) => db_service::riders::get_rider_by_athlete_id(athlete_id).await,
+        None => db_service::riders::get_primary_rider().await,
+    }
+    .ok_or(WebhookError::UnknownRider)?;
+
+    tracing::info!("New beacon url for {}: {}", rider.name, beacon_url);
+    db_service::riders::set_beacon_url(rider.id, Some(beacon_url)).await;
+    beacon_service::beacon_loop::wake(rider.id);
+    Ok(())
+}
+
+// requests are signed as `sha256=<hex hmac>` over "{timestamp}.{body}" with WH_SIGNING_SECRET
+async fn verify_signature(headers: &HeaderMap, body: &[u8]) -> Result<(), WebhookError> {
+    let secret = shared_lib::env_utils::get_webhook_signing_secret()
+        .ok_or(WebhookError::SigningNotConfigured)?;
+
+    let timestamp = get_header(headers, TIMESTAMP_HEADER)?
+        .parse::<i64>()
+        .map_err(|_| WebhookError::InvalidTimestamp)?;
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default();
+    if !is_fresh_timestamp(now, timestamp) {
+        return Err(WebhookError::StaleTimestamp);
+    }
+
+    let signature = get_header(headers, SIGNATURE_HEADER)?;
+    let signature = signature
+        .strip_prefix("sha256=")
+        .ok_or(WebhookError::InvalidSignature)?;
+
+    let mut message = format!("{timestamp}.").into_bytes();
+    message.extend_from_slice(body);
+    if !shared_lib::utils::verify_hmac_sha256(&secret, &message, signature) {
+        return Err(WebhookError::InvalidSignature);
+    }
+
+    // signatures are remembered in the db for as long as their timestamp is accepted, so a captured
+    // request can't be replayed against this or any other instance
+    let expires_at = timestamp + TIMESTAMP_TOLERANCE_SECS;
+    match db_service::webhook_signatures::claim(&signature.to_lowercase(), expires_at).await {
+        Ok(true) => {}
+        Ok(false) => return Err(WebhookError::ReplayedRequest),
+        Err(e) => {
+            tracing::error!("Failed to record webhook signature: {:?}", e);
+            return Err(WebhookError::ReplayCheckFailed);
+        }
+    }
+
+    Ok(())
+}
+
+// the timestamp header isn't trusted until the signature is checked, so this can't overflow on any value
+fn is_fresh_timestamp(now: i64, timestamp: i64) -> bool {
+    now.abs_diff(timestamp) <= TIMESTAMP_TOLERANCE_SECS as u64
+}
+
+fn get_header<'a>(headers: &'a HeaderMap, name: &'static str) -> Result<&'a str, WebhookError> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .ok_or(WebhookError::MissingHeader(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_fresh_timestamp, TIMESTAMP_TOLERANCE_SECS};
+
+    const NOW: i64 = 1_700_000_000;
+
+    #[test]
+    fn timestamps_within_the_tolerance_are_fresh() {
+        assert!(is_fresh_timestamp(NOW, NOW));
+        assert!(is_fresh_timestamp(NOW, NOW - TIMESTAMP_TOLERANCE_SECS));
+        assert!(is_fresh_timestamp(NOW, NOW + TIMESTAMP_TOLERANCE_SECS));
+        assert!(!is_fresh_timestamp(NOW, NOW - TIMESTAMP_TOLERANCE_SECS - 1));
+        assert!(!is_fresh_timestamp(NOW, NOW + TIMESTAMP_TOLERANCE_SECS + 1));
+    }
+
+    #[test]
+    fn extreme_timestamps_are_stale() {
+        assert!(!is_fresh_timestamp(NOW, i64::MIN));
+        assert!(!is_fresh_timestamp(NOW, i64::MAX));
+        assert!(!is_fresh_timestamp(0, i64::MIN));
+    }
 }